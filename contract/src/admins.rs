@@ -1,122 +1,305 @@
+use crate::errors::{require, FactoryError};
+use crate::legacy::TokenFactoryV0;
+use crate::storage::StorageKey;
 use crate::*;
 
-#[near_bindgen]
-impl TokenFactory {
-    pub fn reset(&mut self) {
-        assert!(env::state_exists(), "The contract is not initialized");
-        self.assert_admin();
-        self.tokens.clear();
-    }
+/// Version of the top-level layout, bump it with every layout change
+/// and add a branch reading the previous layout to `migrate`.
+pub const STATE_VERSION: u32 = 1;
+// kept outside the contract struct so it can be read whatever the layout is
+const STATE_VERSION_KEY: &[u8] = b"stateversion";
+/// Allocations deleted per call of the cleanup methods when no limit is given
+pub const DEFAULT_CLEANUP_LIMIT: u64 = 100;
 
-    pub fn unregister(&mut self, ft_contract: AccountId) {
-        assert!(env::state_exists(), "The contract is not initialized");
-        self.assert_admin();
-        self.tokens.remove(&ft_contract);
+/// Outcome of one call of a paginated cleanup
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CleanupReport {
+    // false when the method must be called again to finish
+    pub done: bool,
+    pub bytes_freed: u64,
+}
 
-        let state = self
-            .tokens
-            .get(&ft_contract)
-            .expect("ft_contract not found!");
-
-        for allocator in state.allocations.keys_as_vector().iter() {
-            let mut user_tokens = self
-                .user_token_map
-                .get(&allocator)
-                .expect("user_tokens not found");
-            user_tokens.remove(&ft_contract);
-            self.user_token_map.insert(&allocator, &user_tokens);
+impl CleanupReport {
+    pub(crate) fn new(done: bool, storage_before: StorageUsage) -> Self {
+        CleanupReport {
+            done,
+            bytes_freed: storage_before.saturating_sub(env::storage_usage()),
         }
     }
+}
 
-    pub fn clear_metadata(&mut self, ft_contract: AccountId) {
-        assert!(env::state_exists(), "The contract is not initialized");
-        self.assert_admin();
-        let mut token = self.tokens.remove(&ft_contract).unwrap();
-        token.ft_metadata = None;
-        token.allocations.clear();
-        self.tokens.insert(&ft_contract, &token);
-    }
+pub(crate) fn read_state_version() -> u32 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|raw| u32::try_from_slice(&raw).unwrap())
+        .unwrap_or(0)
+}
+
+pub(crate) fn write_state_version(version: u32) {
+    env::storage_write(STATE_VERSION_KEY, &version.try_to_vec().unwrap());
+}
+
+fn read_layout<T: BorshDeserialize>() -> T {
+    env::state_read().unwrap_or_else(|| FactoryError::StateNotFound.panic())
+}
 
-    fn assert_admin(&self) {
-        assert!(
-            self.admins.contains(&env::predecessor_account_id()),
-            "Function called not from the contract admin",
+#[near_bindgen]
+impl TokenFactory {
+    // removals move the last token into the freed slot, which a running
+    // migration would skip
+    pub(crate) fn assert_no_migration(&self) {
+        require(
+            !self.internal_migration_running(),
+            FactoryError::MigrationInProgress,
         );
     }
 
-    fn assert_owner_id(&self) {
-        assert!(
+    pub(crate) fn assert_owner_id(&self) {
+        require(
             env::predecessor_account_id() == self.owner_id,
-            "Function called not from the contract owner",
+            FactoryError::NotOwner,
         );
     }
 
-    pub fn add_admin(&mut self, account_id: AccountId) {
-        self.assert_owner_id();
-        self.admins.insert(&account_id);
+    /// Upgrades the contract state from the version it was stored with to
+    /// `STATE_VERSION`. Called by the owner after deploying new code; panics
+    /// when the state is already current, so it can not run twice.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let version = read_state_version();
+        require(version < STATE_VERSION, FactoryError::AlreadyMigrated);
+
+        let contract: Self = match version {
+            // the registry deployed before the version key existed
+            0 => Self::from(read_layout::<TokenFactoryV0>()),
+            _ => FactoryError::UnknownStateVersion.panic(),
+        };
+        contract.assert_owner_id();
+
+        write_state_version(STATE_VERSION);
+        contract
     }
 
-    pub fn remove_admin(&mut self, account_id: AccountId) {
-        self.assert_owner_id();
-        self.admins.remove(&account_id);
+    pub fn get_state_version(&self) -> u32 {
+        read_state_version()
     }
+}
 
-    //Update user_tokens_map for existing tokens
-    #[private]
-    #[init(ignore_state)]
-    pub fn migrate() -> Self {
-        let old_state: OldTokenFactory = env::state_read().expect("failed");
-        let new_state = Self {
-            owner_id: old_state.owner_id,
-            admins: old_state.admins,
-            tokens: old_state.tokens,
-            user_token_map: LookupMap::new(b"tokenmap".to_vec()),
-        };
+impl TokenFactory {
+    /// Unregisters every token, newest first, deleting up to `limit` (default
+    /// `DEFAULT_CLEANUP_LIMIT`) allocations per call. Call again until `done`.
+    /// `token_count` carries the tokens removed over the calls.
+    pub(crate) fn internal_reset(
+        &mut self,
+        limit: Option<u64>,
+        token_count: &mut u64,
+    ) -> CleanupReport {
+        self.assert_no_migration();
+        let storage_before = env::storage_usage();
+        let mut budget = limit.unwrap_or(DEFAULT_CLEANUP_LIMIT);
 
-        new_state
-    }
-
-    pub fn migrate_data(&mut self, from_index: u64, limit: u64) {
-        self.assert_admin();
-        let contract_ids = self.tokens.keys_as_vector();
-        for index in from_index..std::cmp::min(from_index + limit, contract_ids.len()) {
-            let contract_id = contract_ids.get(index).unwrap();
-            let state = self.tokens.get(&contract_id).unwrap();
-            for (allocator, _) in state.allocations.to_vec() {
-                let mut tokens = self
-                    .user_token_map
-                    .get(&allocator)
-                    .unwrap_or(UnorderedSet::new(
-                        format!("{}#{}", allocator, env::block_timestamp()).as_bytes(),
-                    ));
-
-                tokens.insert(&contract_id);
-                env::log(
-                    format!("account_id: {:#?} tokens {:#?}", allocator, tokens.to_vec())
-                        .as_bytes(),
-                );
-                self.user_token_map.insert(&allocator, &tokens);
+        // the budget counts deleted allocations and tokens
+        while budget > 0 {
+            let ft_contract = match last_key(&self.tokens) {
+                Some(ft_contract) => ft_contract,
+                None => break,
+            };
+            let mut token = self.internal_get_token(&ft_contract);
+            budget -= self.internal_remove_allocations(&mut token, budget);
+            if budget == 0 || self.internal_has_allocatees(&token) {
+                self.internal_set_token(token);
+                break;
             }
+            self.internal_remove_token(token);
+            *token_count += 1;
+            budget -= 1;
+        }
+
+        let done = self.tokens.is_empty();
+        if done {
+            self.record_event(FactoryEvent::RegistryReset {
+                token_count: *token_count,
+            });
+        }
+        CleanupReport::new(done, storage_before)
+    }
+
+    /// Deletes the token with its allocations and index entries, up to `limit`
+    /// (default `DEFAULT_CLEANUP_LIMIT`) allocations per call. The token stays
+    /// registered until a call reports `done`.
+    pub(crate) fn internal_unregister(
+        &mut self,
+        ft_contract: AccountId,
+        limit: Option<u64>,
+    ) -> CleanupReport {
+        self.assert_no_migration();
+        let storage_before = env::storage_usage();
+        let mut token = self.internal_get_token(&ft_contract);
+
+        self.internal_remove_allocations(&mut token, limit.unwrap_or(DEFAULT_CLEANUP_LIMIT));
+        let done = !self.internal_has_allocatees(&token);
+        if done {
+            self.internal_remove_token(token);
+        } else {
+            self.internal_set_token(token);
+        }
+        CleanupReport::new(done, storage_before)
+    }
+
+    /// Deletes the metadata and allocations of the token, keeping it registered.
+    /// Paginated like `internal_unregister`.
+    pub(crate) fn internal_clear_metadata(
+        &mut self,
+        ft_contract: AccountId,
+        limit: Option<u64>,
+    ) -> CleanupReport {
+        let storage_before = env::storage_usage();
+        let mut token = self.internal_get_token(&ft_contract);
+
+        self.internal_remove_allocations(&mut token, limit.unwrap_or(DEFAULT_CLEANUP_LIMIT));
+        let done = !self.internal_has_allocatees(&token);
+        if done {
+            token.ft_metadata = None;
+        }
+        self.internal_set_token(token);
+
+        if done {
+            self.record_event(FactoryEvent::MetadataCleared { ft_contract });
         }
+        CleanupReport::new(done, storage_before)
     }
 
-    pub fn internal_add_user_token(&mut self, account_id: AccountId, token_id: TokenId) {
+    pub(crate) fn internal_add_creator_token(
+        &mut self,
+        creator: AccountId,
+        id: u64,
+        token_id: TokenId,
+    ) {
+        let mut tokens = self
+            .creator_token_map
+            .get(&creator)
+            .unwrap_or_else(|| TreeMap::new(StorageKey::creator_tokens(&creator)));
+
+        tokens.insert(&id, &token_id);
+        self.creator_token_map.insert(&creator, &tokens);
+    }
+
+    pub(crate) fn internal_add_user_token(
+        &mut self,
+        account_id: AccountId,
+        id: u64,
+        token_id: TokenId,
+    ) {
         let mut tokens = self
             .user_token_map
             .get(&account_id)
-            .unwrap_or(UnorderedSet::new(
-                format!("{}#{}", account_id, env::block_timestamp()).as_bytes(),
-            ));
-
-        tokens.insert(&token_id);
-        env::log(
-            format!(
-                "account_id: {:#?} tokens {:#?}",
-                account_id,
-                tokens.to_vec()
-            )
-            .as_bytes(),
-        );
+            .unwrap_or_else(|| TreeMap::new(StorageKey::user_tokens(&account_id)));
+
+        tokens.insert(&id, &token_id);
         self.user_token_map.insert(&account_id, &tokens);
     }
+
+    pub(crate) fn internal_remove_creator_token(&mut self, creator: &AccountId, token: &State) {
+        if let Some(mut tokens) = self.creator_token_map.get(creator) {
+            remove_indexed_token(&mut tokens, token);
+            if tokens.len() == 0 {
+                self.creator_token_map.remove(creator);
+            } else {
+                self.creator_token_map.insert(creator, &tokens);
+            }
+        }
+    }
+
+    /// Deletes up to `limit` allocations of the token, last first, with their
+    /// allocatee index entries, then the index entries of the allocations
+    /// compaction dropped. Returns the number deleted.
+    pub(crate) fn internal_remove_allocations(&mut self, token: &mut State, limit: u64) -> u64 {
+        let mut removed = 0;
+        while removed < limit {
+            let allocatee = match last_key(&token.allocations) {
+                Some(allocatee) => allocatee,
+                None => break,
+            };
+            token.allocations.remove(&allocatee);
+            self.internal_remove_user_token(&allocatee, token);
+            removed += 1;
+        }
+
+        if let Some(mut allocatees) = self.compacted_allocatees.get(&token.ft_contract) {
+            while removed < limit {
+                let allocatee = match allocatees.pop() {
+                    Some(allocatee) => allocatee,
+                    None => break,
+                };
+                self.internal_remove_user_token(&allocatee, token);
+                removed += 1;
+            }
+            if allocatees.is_empty() {
+                self.compacted_allocatees.remove(&token.ft_contract);
+            } else {
+                self.compacted_allocatees
+                    .insert(&token.ft_contract, &allocatees);
+            }
+        }
+        removed
+    }
+
+    /// Whether `internal_remove_allocations` has entries of the token left to delete
+    pub(crate) fn internal_has_allocatees(&self, token: &State) -> bool {
+        !token.allocations.is_empty() || self.compacted_allocatees.contains_key(&token.ft_contract)
+    }
+
+    /// Deletes a token whose allocations are already removed, with its indexes
+    pub(crate) fn internal_remove_token(&mut self, token: State) {
+        let ft_contract = token.ft_contract.clone();
+        if token.allocation_initialized == 0 {
+            self.internal_release_unfinished(&token.creator);
+        }
+        // the last record moves to the slot of the removed one, a baseline
+        // record is stored with its id first since that id is its slot
+        let moved = self.tokens.keys_as_vector().get(self.tokens.len() - 1);
+        if let Some(moved) = moved.filter(|moved| moved != &ft_contract) {
+            if let Some(VersionedState::V0(_)) = self.tokens.get(&moved) {
+                let state = self.internal_get_token(&moved);
+                self.internal_set_token(state);
+            }
+        }
+        self.tokens.remove(&ft_contract);
+        self.review_queue.remove(&ft_contract);
+        self.internal_remove_moderation(&ft_contract);
+        self.internal_remove_creator_token(&token.creator, &token);
+        if self.token_order.get(&token.id).as_ref() == Some(&ft_contract) {
+            self.token_order.remove(&token.id);
+        }
+
+        self.record_event(FactoryEvent::TokenUnregistered { ft_contract });
+    }
+
+    pub(crate) fn internal_remove_user_token(&mut self, account_id: &AccountId, token: &State) {
+        // the allocatee index is only filled once allocations are initialized
+        if let Some(mut tokens) = self.user_token_map.get(account_id) {
+            remove_indexed_token(&mut tokens, token);
+            if tokens.len() == 0 {
+                self.user_token_map.remove(account_id);
+            } else {
+                self.user_token_map.insert(account_id, &tokens);
+            }
+        }
+    }
+}
+
+/// Removes the token from an index by id. The id is checked against the token
+/// like for `token_order`.
+fn remove_indexed_token(tokens: &mut TreeMap<u64, TokenId>, token: &State) {
+    if tokens.get(&token.id).as_ref() == Some(&token.ft_contract) {
+        tokens.remove(&token.id);
+    }
+}
+
+// removing the last entry of an UnorderedMap moves no other entry
+fn last_key<K: BorshSerialize + BorshDeserialize, V: BorshSerialize + BorshDeserialize>(
+    map: &UnorderedMap<K, V>,
+) -> Option<K> {
+    let keys = map.keys_as_vector();
+    keys.len().checked_sub(1).and_then(|index| keys.get(index))
 }
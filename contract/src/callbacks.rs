@@ -1,5 +1,8 @@
 use near_sdk::ext_contract;
 
+use crate::errors::{require, FactoryError};
+use crate::events::{FactoryEvent, LifecycleStep};
+use crate::portfolio::{promise_result_json, DeployerAccount, Portfolio, PortfolioItem};
 use crate::*;
 
 #[ext_contract(ext_self)]
@@ -8,81 +11,163 @@ pub trait ExtTokenFactory {
     fn on_ft_deployer_deployed(&mut self, ft_contract: AccountId) -> bool;
     fn on_token_issued(&mut self, ft_contract: AccountId) -> bool;
     fn on_allocation_init(&mut self, ft_contract: AccountId) -> bool;
+    fn on_portfolio_fetched(
+        &self,
+        account_id: AccountId,
+        token_ids: Vec<TokenId>,
+        next_cursor: Option<u64>,
+    ) -> Portfolio;
+}
+
+#[ext_contract(ext_deployer)]
+pub trait ExtTokenDeployer {
+    fn check_account(&self, account_id: AccountId) -> Value;
+}
+
+#[ext_contract(ext_ft)]
+pub trait ExtFungibleToken {
+    fn ft_balance_of(&self, account_id: AccountId) -> WrappedBalance;
 }
 
 #[near_bindgen]
 impl TokenFactory {
     #[private]
     pub fn on_ft_contract_deployed(&mut self, ft_contract: AccountId) -> bool {
-        env::log(format!("promise_result_count = {}", env::promise_results_count()).as_bytes());
-        match env::promise_result(0) {
+        let success = match env::promise_result(0) {
             PromiseResult::Successful(_) => {
-                let mut token = self.tokens.remove(&ft_contract).unwrap_or_default();
-                assert!(
+                let mut token = self.internal_get_token(&ft_contract);
+                require(
                     token.ft_contract_deployed == 0,
-                    "State ft_contract_deployed is invalid",
+                    FactoryError::StepAlreadyCompleted(LifecycleStep::FtContractDeployment),
                 );
                 token.ft_contract_deployed = 1;
-                self.tokens.insert(&ft_contract, &token);
+                self.internal_set_token(token);
                 true
             }
             _ => false,
-        }
+        };
+
+        self.record_event(FactoryEvent::LifecycleStep {
+            ft_contract,
+            step: LifecycleStep::FtContractDeployment,
+            success,
+        });
+        success
     }
 
     #[private]
     pub fn on_ft_deployer_deployed(&mut self, ft_contract: AccountId) -> bool {
-        env::log(format!("promise_result_count = {}", env::promise_results_count()).as_bytes());
-        // format!("fasfas");
-        match env::promise_result(0) {
+        let success = match env::promise_result(0) {
             PromiseResult::Successful(_) => {
-                let mut token = self.tokens.remove(&ft_contract).unwrap_or_default();
-                assert!(
+                let mut token = self.internal_get_token(&ft_contract);
+                require(
                     token.deployer_contract_deployed == 0,
-                    "State deployer_contract_deployed is invalid",
+                    FactoryError::StepAlreadyCompleted(LifecycleStep::DeployerContractDeployment),
                 );
                 token.deployer_contract_deployed = 1;
-                self.tokens.insert(&ft_contract, &token);
+                self.internal_set_token(token);
                 true
             }
             _ => false,
-        }
+        };
+
+        self.record_event(FactoryEvent::LifecycleStep {
+            ft_contract,
+            step: LifecycleStep::DeployerContractDeployment,
+            success,
+        });
+        success
     }
 
     #[private]
     pub fn on_token_issued(&mut self, ft_contract: AccountId) -> bool {
-        env::log(format!("promise_result_count = {}", env::promise_results_count()).as_bytes());
-        match env::promise_result(0) {
+        let success = match env::promise_result(0) {
             PromiseResult::Successful(_) => {
-                let mut token = self.tokens.remove(&ft_contract).unwrap_or_default();
-                assert!(token.ft_issued == 0, "State ft_issued is invalid",);
+                let mut token = self.internal_get_token(&ft_contract);
+                require(
+                    token.ft_issued == 0,
+                    FactoryError::StepAlreadyCompleted(LifecycleStep::FtIssuance),
+                );
                 token.ft_issued = 1;
-                self.tokens.insert(&ft_contract, &token);
+                self.internal_set_token(token);
                 true
             }
             _ => false,
-        }
+        };
+
+        self.record_event(FactoryEvent::LifecycleStep {
+            ft_contract,
+            step: LifecycleStep::FtIssuance,
+            success,
+        });
+        success
     }
 
     #[private]
     pub fn on_allocation_init(&mut self, ft_contract: AccountId) -> bool {
-        env::log(format!("promise_result_count = {}", env::promise_results_count()).as_bytes());
-        match env::promise_result(0) {
+        let success = match env::promise_result(0) {
             PromiseResult::Successful(_) => {
-                let mut token = self.tokens.remove(&ft_contract).unwrap_or_default();
-                assert!(
+                let mut token = self.internal_get_token(&ft_contract);
+                require(
                     token.allocation_initialized == 0,
-                    "State allocation_initialized is invalid",
+                    FactoryError::StepAlreadyCompleted(LifecycleStep::AllocationInit),
                 );
+                self.internal_release_unfinished(&token.creator);
                 token.allocation_initialized = 1;
-                // TODO: this is temporary. It needs to be uncommented at some point
-                // token.ft_metadata = None;
-                // token.allocations.clear();
-                self.tokens.insert(&ft_contract, &token);
+                // the allocations are dropped later by `compact_token`, which
+                // needs more gas than this callback has
+                let allocatee_count = token.allocations.len();
+                self.internal_set_token(token);
+                self.record_event(FactoryEvent::AllocationInitialized {
+                    ft_contract: ft_contract.clone(),
+                    allocatee_count,
+                });
                 true
             }
             _ => false,
+        };
+
+        self.record_event(FactoryEvent::LifecycleStep {
+            ft_contract,
+            step: LifecycleStep::AllocationInit,
+            success,
+        });
+        success
+    }
+
+    #[private]
+    pub fn on_portfolio_fetched(
+        &self,
+        account_id: AccountId,
+        token_ids: Vec<TokenId>,
+        next_cursor: Option<u64>,
+    ) -> Portfolio {
+        let tokens = token_ids
+            .into_iter()
+            .enumerate()
+            // a token unregistered while the queries ran is left out
+            .filter_map(|(i, ft_contract)| {
+                let token = self.internal_find_token(&ft_contract)?;
+                let account: Option<DeployerAccount> = promise_result_json(2 * i as u64);
+                let balance: Option<WrappedBalance> = promise_result_json(2 * i as u64 + 1);
+
+                Some(PortfolioItem {
+                    ft_contract,
+                    ft_deployer: token.ft_deployer.clone(),
+                    symbol: token.symbol(),
+                    decimals: token.decimals(),
+                    allocated: account.as_ref().map(|a| a.allocated_num),
+                    claimed: account.as_ref().map(|a| a.claimed),
+                    claimable: account.as_ref().map(|a| a.claimable_amount),
+                    balance,
+                })
+            })
+            .collect();
+
+        Portfolio {
+            account_id,
+            tokens,
+            next_cursor,
         }
     }
 }
-
@@ -0,0 +1,161 @@
+use near_sdk::CryptoHash;
+
+use crate::admins::{CleanupReport, DEFAULT_CLEANUP_LIMIT};
+use crate::errors::{require, FactoryError};
+use crate::storage::StorageKey;
+use crate::*;
+
+/// What the factory keeps of a token once its allocations are initialized
+/// on the deployer. Amounts cover the allocations dropped so far.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CompactSummary {
+    // sha256 of the Borsh serialized `FTMetadata`
+    pub metadata_hash: CryptoHash,
+    pub token_name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: Balance,
+
+    pub allocation_count: u64,
+    pub treasury_amount: Balance,
+    pub initial_release_amount: Balance,
+
+    pub storage_refunded: Balance,
+    // None while allocations are left to drop
+    pub completed_at: Option<Timestamp>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WrappedCompactSummary {
+    pub metadata_hash: Base64VecU8,
+    pub token_name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: WrappedBalance,
+    pub allocation_count: u64,
+    pub treasury_amount: WrappedBalance,
+    pub initial_release_amount: WrappedBalance,
+    pub storage_refunded: WrappedBalance,
+    pub completed_at: Option<WrappedTimestamp>,
+}
+
+impl From<CompactSummary> for WrappedCompactSummary {
+    fn from(summary: CompactSummary) -> Self {
+        WrappedCompactSummary {
+            metadata_hash: Base64VecU8::from(summary.metadata_hash.to_vec()),
+            token_name: summary.token_name,
+            symbol: summary.symbol,
+            decimals: summary.decimals,
+            total_supply: WrappedBalance::from(summary.total_supply),
+            allocation_count: summary.allocation_count,
+            treasury_amount: WrappedBalance::from(summary.treasury_amount),
+            initial_release_amount: WrappedBalance::from(summary.initial_release_amount),
+            storage_refunded: WrappedBalance::from(summary.storage_refunded),
+            completed_at: summary.completed_at.map(WrappedTimestamp::from),
+        }
+    }
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Drops the metadata and up to `limit` (default `DEFAULT_CLEANUP_LIMIT`)
+    /// allocations of a token whose allocations are initialized on the deployer,
+    /// and refunds the freed storage stake to the creator. Anyone can call it,
+    /// again until `done`.
+    pub fn compact_token(&mut self, ft_contract: AccountId, limit: Option<u64>) -> CleanupReport {
+        self.assert_not_paused(PausableOperation::Compaction);
+        let storage_before = env::storage_usage();
+        let mut token = self.internal_get_token(&ft_contract);
+        require(
+            token.allocation_initialized == 1,
+            FactoryError::AllocationNotInitialized,
+        );
+        require(
+            token
+                .compacted
+                .as_ref()
+                .map(|summary| summary.completed_at.is_none())
+                .unwrap_or(true),
+            FactoryError::TokenAlreadyCompacted,
+        );
+
+        if token.compacted.is_none() {
+            let metadata = token
+                .ft_metadata
+                .take()
+                .unwrap_or_else(|| FactoryError::MetadataNotFound.panic());
+            let mut metadata_hash = CryptoHash::default();
+            metadata_hash.copy_from_slice(&env::sha256(&metadata.try_to_vec().unwrap()));
+            token.compacted = Some(CompactSummary {
+                metadata_hash,
+                token_name: metadata.token_name,
+                symbol: metadata.symbol,
+                decimals: metadata.decimals,
+                total_supply: metadata.total_supply,
+                allocation_count: 0,
+                treasury_amount: 0,
+                initial_release_amount: 0,
+                storage_refunded: 0,
+                completed_at: None,
+            });
+        }
+
+        let allocations: Vec<(AccountId, TokenAllocation)> = token
+            .allocations
+            .iter()
+            .take(limit.unwrap_or(DEFAULT_CLEANUP_LIMIT) as usize)
+            .collect();
+        // the allocatee index entries stay, their accounts are kept to delete
+        // them with the token
+        let mut allocatees = self
+            .compacted_allocatees
+            .get(&ft_contract)
+            .unwrap_or_else(|| {
+                Vector::new(StorageKey::compacted_allocatees(token.id, &ft_contract))
+            });
+        let (mut treasury_amount, mut initial_release_amount) = (0, 0);
+        for (account_id, allocation) in allocations.iter() {
+            if account_id == TOKENHUB_TREASURY {
+                treasury_amount += token.amount_of(allocation.allocated_percent);
+            }
+            initial_release_amount += token.amount_of(allocation.initial_release);
+            token.allocations.remove(account_id);
+            allocatees.push(account_id);
+        }
+        let summary = token.compacted.as_mut().unwrap();
+        summary.treasury_amount += treasury_amount;
+        summary.initial_release_amount += initial_release_amount;
+        summary.allocation_count += allocations.len() as u64;
+
+        if !allocatees.is_empty() {
+            self.compacted_allocatees.insert(&ft_contract, &allocatees);
+        }
+        let done = token.allocations.is_empty();
+        if done {
+            summary.completed_at = Some(env::block_timestamp());
+        }
+        let creator = token.creator.clone();
+        self.internal_set_token(token);
+
+        // the refund is recorded after measuring, it does not change the size
+        let report = CleanupReport::new(done, storage_before);
+        let refund = report.bytes_freed as Balance * env::storage_byte_cost();
+        let mut token = self.internal_get_token(&ft_contract);
+        let summary = token.compacted.as_mut().unwrap();
+        summary.storage_refunded += refund;
+        let storage_refunded = summary.storage_refunded;
+        self.internal_set_token(token);
+        if refund > 0 {
+            Promise::new(creator).transfer(refund);
+        }
+
+        if done {
+            self.record_event(FactoryEvent::TokenCompacted {
+                ft_contract,
+                storage_refunded: WrappedBalance::from(storage_refunded),
+            });
+        }
+        report
+    }
+}
@@ -0,0 +1,43 @@
+use crate::*;
+
+const DEFAULT_ACCOUNT_FUNDING: Balance = 4_000_000_000_000_000_000_000_000;
+
+/// Amounts charged on top of the storage a registration uses
+#[derive(BorshDeserialize, BorshSerialize, Clone, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FactoryConfig {
+    // transferred to the accounts by `create_ft_contract` and `create_deployer_contract`
+    pub ft_account_funding: WrappedBalance,
+    pub deployer_account_funding: WrappedBalance,
+    // kept by the factory
+    pub registration_fee: WrappedBalance,
+}
+
+impl Default for FactoryConfig {
+    fn default() -> Self {
+        FactoryConfig {
+            ft_account_funding: WrappedBalance::from(DEFAULT_ACCOUNT_FUNDING),
+            deployer_account_funding: WrappedBalance::from(DEFAULT_ACCOUNT_FUNDING),
+            registration_fee: WrappedBalance::from(0),
+        }
+    }
+}
+
+impl FactoryConfig {
+    pub fn account_funding(&self) -> Balance {
+        u128::from(self.ft_account_funding) + u128::from(self.deployer_account_funding)
+    }
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    pub fn get_config(&self) -> FactoryConfig {
+        self.config.clone()
+    }
+
+    pub fn set_config(&mut self, config: FactoryConfig) {
+        self.assert_role(Role::FeeManager);
+        self.config = config.clone();
+        self.record_event(FactoryEvent::ConfigChanged { config });
+    }
+}
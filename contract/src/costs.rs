@@ -0,0 +1,237 @@
+use crate::creators::CreatorUsage;
+use crate::errors::{require, FactoryError};
+use crate::events::CHANGE_FEED_CAPACITY;
+use crate::review::ReviewRequest;
+use crate::validation::RegistrationInput;
+use crate::*;
+
+// counted by the runtime for every record on top of its key and value
+const RECORD_OVERHEAD: StorageUsage = 40;
+// u64 index of a vector element, used as key suffix and as value
+const INDEX_LEN: StorageUsage = 8;
+
+/// What the creator of a token paid, summed over every charged call
+#[derive(BorshDeserialize, BorshSerialize, Clone, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenCost {
+    pub storage_bytes: StorageUsage,
+    pub storage_cost: WrappedBalance,
+    pub account_funding: WrappedBalance,
+    pub fee: WrappedBalance,
+    pub total: WrappedBalance,
+}
+
+impl TokenCost {
+    pub fn new(storage_bytes: StorageUsage, account_funding: Balance, fee: Balance) -> Self {
+        let storage_cost = storage_bytes as Balance * env::storage_byte_cost();
+        TokenCost {
+            storage_bytes,
+            storage_cost: WrappedBalance::from(storage_cost),
+            account_funding: WrappedBalance::from(account_funding),
+            fee: WrappedBalance::from(fee),
+            total: WrappedBalance::from(storage_cost + account_funding + fee),
+        }
+    }
+
+    pub fn add(&self, other: &TokenCost) -> Self {
+        TokenCost::new(
+            self.storage_bytes + other.storage_bytes,
+            u128::from(self.account_funding) + u128::from(other.account_funding),
+            u128::from(self.fee) + u128::from(other.fee),
+        )
+    }
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// What `register` charges for these arguments: the storage the registration
+    /// adds, the configured account funding and the fee. `creator` is the
+    /// account that will sign `register`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn quote_registration_cost(
+        &self,
+        ft_contract: AccountId,
+        deployer_contract: AccountId,
+        total_supply: WrappedBalance,
+        token_name: String,
+        symbol: String,
+        icon: Option<String>,
+        reference: Option<String>,
+        reference_hash: Option<Base64VecU8>,
+        allocations: TokenAllocationInput,
+        decimals: u8,
+        creator: AccountId,
+    ) -> TokenCost {
+        let input = RegistrationInput {
+            ft_contract,
+            deployer_contract,
+            total_supply: total_supply.into(),
+            token_name,
+            symbol,
+            icon,
+            reference,
+            reference_hash,
+            allocations,
+            decimals,
+        };
+        self.internal_quote_registration(&input, creator)
+    }
+}
+
+impl TokenFactory {
+    pub(crate) fn internal_quote_registration(
+        &self,
+        input: &RegistrationInput,
+        creator: AccountId,
+    ) -> TokenCost {
+        TokenCost::new(
+            self.internal_registration_bytes(input, creator),
+            self.config.account_funding(),
+            self.config.registration_fee.into(),
+        )
+    }
+
+    /// Charges the caller for the storage added since `storage_before` plus
+    /// `account_funding` and `fee`, and refunds the rest of the attached deposit
+    pub(crate) fn internal_charge(
+        &self,
+        storage_before: StorageUsage,
+        account_funding: Balance,
+        fee: Balance,
+    ) -> TokenCost {
+        let cost = TokenCost::new(
+            env::storage_usage().saturating_sub(storage_before),
+            account_funding,
+            fee,
+        );
+        let total: Balance = cost.total.into();
+        let deposit = env::attached_deposit();
+        require(deposit >= total, FactoryError::InsufficientDeposit);
+        if deposit > total {
+            Promise::new(env::predecessor_account_id()).transfer(deposit - total);
+        }
+        cost
+    }
+
+    /// Bytes `register` adds to the storage, record by record
+    fn internal_registration_bytes(
+        &self,
+        input: &RegistrationInput,
+        creator: AccountId,
+    ) -> StorageUsage {
+        let token_id_len = serialized_len(&input.ft_contract);
+        let token = self.internal_new_token(input, creator.clone());
+        let mut bytes = map_entry_bytes(
+            collection_prefix_len(&self.tokens, 0),
+            token_id_len,
+            serialized_len(&VersionedState::from(token)),
+        );
+
+        // the allocations are new, their prefix is unique to the registration
+        let allocations_prefix_len = serialized_len(&StorageKey::allocations(
+            self.next_token_id,
+            &input.ft_contract,
+        )) + 1;
+        for (account_id, allocation) in &input.allocations {
+            bytes += map_entry_bytes(
+                allocations_prefix_len,
+                serialized_len(account_id),
+                serialized_len(&TokenAllocation::from(allocation.clone())),
+            );
+        }
+
+        // the usage of the creator, stored with its first counted registration
+        if self.creator_usage.get(&creator).is_none() {
+            bytes += RECORD_OVERHEAD
+                + collection_prefix_len(&self.creator_usage, 0)
+                + serialized_len(&creator)
+                + serialized_len(&CreatorUsage::default());
+        }
+
+        // the creator index, created with the first token of a creator
+        let creator_tokens = match self.creator_token_map.get(&creator) {
+            Some(tokens) => tokens,
+            None => {
+                let tokens = TreeMap::new(StorageKey::creator_tokens(&creator));
+                bytes += RECORD_OVERHEAD
+                    + collection_prefix_len(&self.creator_token_map, 0)
+                    + serialized_len(&creator)
+                    + serialized_len(&tokens);
+                tokens
+            }
+        };
+        bytes += tree_entry_bytes(&creator_tokens, token_id_len);
+
+        // the creation order
+        bytes += tree_entry_bytes(&self.token_order, token_id_len);
+
+        // the review request, while curated
+        if self.curated {
+            bytes += map_entry_bytes(
+                collection_prefix_len(&self.review_queue, 0),
+                token_id_len,
+                serialized_len(&ReviewRequest::new(creator.clone())),
+            );
+        }
+
+        // the change feed slot, which replaces the oldest change once the feed is full
+        let record = self.internal_next_change(FactoryEvent::TokenRegistered {
+            id: self.next_token_id,
+            ft_contract: input.ft_contract.clone(),
+            ft_deployer: input.deployer_contract.clone(),
+            creator,
+        });
+        bytes += serialized_len(&record);
+        match self.changes.get(&(record.seq % CHANGE_FEED_CAPACITY)) {
+            Some(replaced) => bytes -= serialized_len(&replaced),
+            None => bytes += RECORD_OVERHEAD + collection_prefix_len(&self.changes, 0) + INDEX_LEN,
+        }
+        bytes
+    }
+}
+
+/// Layout of a `TreeMap` node with no children, as stored by near-sdk
+#[derive(BorshSerialize, Default)]
+struct TreeNode {
+    id: u64,
+    key: u64,
+    lft: Option<u64>,
+    rgt: Option<u64>,
+    ht: u64,
+}
+
+/// Bytes a new id adds to a token index: a value, a tree node and the link
+/// from its parent
+fn tree_entry_bytes(tree: &TreeMap<u64, TokenId>, token_id_len: StorageUsage) -> StorageUsage {
+    let prefix_len = collection_prefix_len(tree, INDEX_LEN as usize);
+    let mut bytes = 2 * RECORD_OVERHEAD
+        + 2 * (prefix_len + INDEX_LEN)
+        + token_id_len
+        + serialized_len(&TreeNode::default());
+    if tree.len() > 0 {
+        bytes += INDEX_LEN;
+    }
+    bytes
+}
+
+/// Bytes a new key adds to an `UnorderedMap`: its index, the key and the value,
+/// the latter two keyed by that index
+fn map_entry_bytes(
+    prefix_len: StorageUsage,
+    key_len: StorageUsage,
+    value_len: StorageUsage,
+) -> StorageUsage {
+    3 * RECORD_OVERHEAD + 3 * prefix_len + 3 * INDEX_LEN + 2 * key_len + value_len
+}
+
+fn serialized_len<T: BorshSerialize>(value: &T) -> StorageUsage {
+    value.try_to_vec().unwrap().len() as StorageUsage
+}
+
+/// Length of the first storage prefix a collection serializes at `offset`. For
+/// maps and sets that is the element index prefix, which is as long as the
+/// prefixes of their keys and values.
+fn collection_prefix_len<T: BorshSerialize>(collection: &T, offset: usize) -> StorageUsage {
+    let serialized = collection.try_to_vec().unwrap();
+    u32::try_from_slice(&serialized[offset..offset + 4]).unwrap() as StorageUsage
+}
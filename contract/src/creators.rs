@@ -0,0 +1,192 @@
+use crate::errors::{require, FactoryError};
+use crate::*;
+
+const DEFAULT_REGISTRATION_WINDOW: Duration = 86_400_000_000_000; // 1 day
+
+/// Caps on the registrations of each creator, None for no cap
+#[derive(BorshDeserialize, BorshSerialize, Clone, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreatorLimits {
+    // registrations whose allocations are not initialized yet
+    pub max_unfinished: Option<u32>,
+    // registrations per `window`, counted from the first one of the window
+    pub max_per_window: Option<u32>,
+    pub window: WrappedDuration,
+}
+
+impl Default for CreatorLimits {
+    fn default() -> Self {
+        CreatorLimits {
+            max_unfinished: None,
+            max_per_window: None,
+            window: WrappedDuration::from(DEFAULT_REGISTRATION_WINDOW),
+        }
+    }
+}
+
+/// Registrations of a creator counted against `CreatorLimits`. Stored with the
+/// first registration or step that changes it; for older creators it is
+/// counted from their tokens then.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default)]
+pub struct CreatorUsage {
+    pub unfinished: u32,
+    pub window_start: Timestamp,
+    pub window_registrations: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WrappedCreatorUsage {
+    // false while the allowlist is enabled and the creator is not on it
+    pub allowed: bool,
+    pub unfinished: u32,
+    // registrations of the current window, 0 once it is over
+    pub window_registrations: u32,
+    pub window_ends_at: Option<WrappedTimestamp>,
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// While enabled, only allowed creators can register. Tokens registered
+    /// before keep going through their steps.
+    pub fn set_allowlist_enabled(&mut self, enabled: bool) {
+        self.assert_owner_id();
+        if self.allowlist_enabled != enabled {
+            self.allowlist_enabled = enabled;
+            self.record_event(FactoryEvent::AllowlistChanged { enabled });
+        }
+    }
+
+    pub fn allow_creator(&mut self, account_id: AccountId) {
+        self.assert_owner_id();
+        if self.allowed_creators.insert(&account_id) {
+            self.record_event(FactoryEvent::CreatorAllowed { account_id });
+        }
+    }
+
+    pub fn disallow_creator(&mut self, account_id: AccountId) {
+        self.assert_owner_id();
+        if self.allowed_creators.remove(&account_id) {
+            self.record_event(FactoryEvent::CreatorDisallowed { account_id });
+        }
+    }
+
+    pub fn set_creator_limits(&mut self, limits: CreatorLimits) {
+        self.assert_owner_id();
+        self.creator_limits = limits.clone();
+        self.record_event(FactoryEvent::CreatorLimitsChanged { limits });
+    }
+
+    pub fn is_allowlist_enabled(&self) -> bool {
+        self.allowlist_enabled
+    }
+
+    pub fn get_allowed_creators(&self, from_index: u64, limit: u64) -> Vec<AccountId> {
+        self.allowed_creators
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+
+    pub fn get_creator_limits(&self) -> CreatorLimits {
+        self.creator_limits.clone()
+    }
+
+    pub fn get_creator_usage(&self, account_id: AccountId) -> WrappedCreatorUsage {
+        let usage = self.internal_current_usage(&account_id);
+        let window_open = usage.window_registrations > 0;
+        WrappedCreatorUsage {
+            allowed: self.internal_creator_allowed(&account_id),
+            unfinished: usage.unfinished,
+            window_registrations: usage.window_registrations,
+            window_ends_at: if window_open {
+                Some(WrappedTimestamp::from(
+                    usage.window_start + u64::from(self.creator_limits.window),
+                ))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl TokenFactory {
+    /// Asserts that `creator` may register another token and returns its usage
+    /// counting that registration, for `register` to store
+    pub(crate) fn internal_check_creator(&self, creator: &AccountId) -> CreatorUsage {
+        require(
+            self.internal_creator_allowed(creator),
+            FactoryError::CreatorNotAllowed,
+        );
+
+        let mut usage = self.internal_current_usage(creator);
+        let limits = &self.creator_limits;
+        require(
+            limits
+                .max_unfinished
+                .map(|max| usage.unfinished < max)
+                .unwrap_or(true),
+            FactoryError::TooManyUnfinishedRegistrations,
+        );
+        require(
+            limits
+                .max_per_window
+                .map(|max| usage.window_registrations < max)
+                .unwrap_or(true),
+            FactoryError::RegistrationLimitReached,
+        );
+
+        if usage.window_registrations == 0 {
+            usage.window_start = env::block_timestamp();
+        }
+        usage.unfinished += 1;
+        usage.window_registrations += 1;
+        usage
+    }
+
+    /// Stops counting an unfinished token of `creator`. Call it before the
+    /// token is stored as finished or removed, an older creator's usage is
+    /// counted from its tokens.
+    pub(crate) fn internal_release_unfinished(&mut self, creator: &AccountId) {
+        let mut usage = self.internal_creator_usage(creator);
+        usage.unfinished = usage.unfinished.saturating_sub(1);
+        self.creator_usage.insert(creator, &usage);
+    }
+
+    fn internal_creator_allowed(&self, creator: &AccountId) -> bool {
+        !self.allowlist_enabled || self.allowed_creators.contains(creator)
+    }
+
+    /// Usage with the window reset once it is over
+    fn internal_current_usage(&self, creator: &AccountId) -> CreatorUsage {
+        let mut usage = self.internal_creator_usage(creator);
+        let window_end = usage
+            .window_start
+            .saturating_add(self.creator_limits.window.into());
+        if env::block_timestamp() >= window_end {
+            usage.window_registrations = 0;
+        }
+        usage
+    }
+
+    fn internal_creator_usage(&self, creator: &AccountId) -> CreatorUsage {
+        self.creator_usage.get(creator).unwrap_or_else(|| {
+            let unfinished = self
+                .creator_token_map
+                .get(creator)
+                .map(|tokens| {
+                    tokens
+                        .iter()
+                        .filter_map(|(_, token)| self.internal_find_token(&token))
+                        .filter(|token| token.allocation_initialized == 0)
+                        .count() as u32
+                })
+                .unwrap_or(0);
+            CreatorUsage {
+                unfinished,
+                ..CreatorUsage::default()
+            }
+        })
+    }
+}
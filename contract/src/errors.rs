@@ -0,0 +1,269 @@
+use std::fmt;
+
+use crate::events::LifecycleStep;
+use crate::pause::PausableOperation;
+use crate::roles::Role;
+use crate::*;
+
+/// Every failure of the factory. Codes are stable: never renumber or reuse them,
+/// add new variants with new codes instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FactoryError {
+    // 1xx: contract and access
+    NotInitialized,
+    NotOwner,
+    NotCreator,
+    StateNotFound,
+    AlreadyMigrated,
+    UnknownStateVersion,
+    MigrationInProgress,
+    NoMigrationRunning,
+    InvalidMigrationTarget,
+    OperationPaused(PausableOperation),
+    MissingRole(Role),
+    InvalidOwner,
+    NoPendingOwner,
+    NotPendingOwner,
+    OperationNotFound,
+    OperationNotReady,
+    OperationAlreadyStarted,
+    CreatorNotAllowed,
+    TimelockDelayTooShort,
+
+    // 2xx: registry
+    TokenNotRegistered,
+    TokenAlreadyRegistered,
+    MetadataNotFound,
+    AllocationNotFound,
+    EmptyModerationReason,
+
+    // 3xx: registration input
+    InsufficientDeposit,
+    ZeroTotalSupply,
+    InvalidFtContract,
+    InvalidFtDeployer,
+    AllocationsExceedSupply,
+    AllocationsNotFullSupply,
+    TreasuryAllocationMissing,
+    AllocationBelowClaimed,
+    VestingIntervalTooLong,
+    EmptyTokenName,
+    EmptySymbol,
+    InvalidDecimals,
+    InvalidReferenceHash,
+    EmptyIcon,
+    SameContractAccounts,
+    InvalidAllocationAccount,
+    ZeroAllocation,
+    InitialReleaseExceedsAllocation,
+    VestingEndBeforeStart,
+    ZeroVestingInterval,
+    VestingStartInPast,
+    VestingDurationTooLong,
+    TooManyAllocatees,
+    TooManyUnfinishedRegistrations,
+    RegistrationLimitReached,
+
+    // 4xx: lifecycle
+    StepAlreadyCompleted(LifecycleStep),
+    AllocationNotInitialized,
+    TokenAlreadyCompacted,
+    AwaitingApproval,
+    NotAwaitingApproval,
+}
+
+impl FactoryError {
+    pub fn code(&self) -> u16 {
+        match self {
+            FactoryError::NotInitialized => 100,
+            FactoryError::NotOwner => 101,
+            FactoryError::NotCreator => 102,
+            FactoryError::StateNotFound => 103,
+            FactoryError::AlreadyMigrated => 104,
+            FactoryError::UnknownStateVersion => 105,
+            FactoryError::MigrationInProgress => 106,
+            FactoryError::NoMigrationRunning => 107,
+            FactoryError::InvalidMigrationTarget => 108,
+            FactoryError::OperationPaused(_) => 109,
+            FactoryError::MissingRole(_) => 110,
+            FactoryError::InvalidOwner => 111,
+            FactoryError::NoPendingOwner => 112,
+            FactoryError::NotPendingOwner => 113,
+            FactoryError::OperationNotFound => 114,
+            FactoryError::OperationNotReady => 115,
+            FactoryError::OperationAlreadyStarted => 116,
+            FactoryError::CreatorNotAllowed => 117,
+            FactoryError::TimelockDelayTooShort => 118,
+
+            FactoryError::TokenNotRegistered => 200,
+            FactoryError::TokenAlreadyRegistered => 201,
+            FactoryError::MetadataNotFound => 202,
+            FactoryError::AllocationNotFound => 203,
+            FactoryError::EmptyModerationReason => 204,
+
+            FactoryError::InsufficientDeposit => 300,
+            FactoryError::ZeroTotalSupply => 301,
+            FactoryError::InvalidFtContract => 302,
+            FactoryError::InvalidFtDeployer => 303,
+            FactoryError::AllocationsExceedSupply => 304,
+            FactoryError::AllocationsNotFullSupply => 305,
+            FactoryError::TreasuryAllocationMissing => 306,
+            FactoryError::AllocationBelowClaimed => 307,
+            FactoryError::VestingIntervalTooLong => 308,
+            FactoryError::EmptyTokenName => 309,
+            FactoryError::EmptySymbol => 310,
+            FactoryError::InvalidDecimals => 311,
+            FactoryError::InvalidReferenceHash => 312,
+            FactoryError::EmptyIcon => 313,
+            FactoryError::SameContractAccounts => 314,
+            FactoryError::InvalidAllocationAccount => 315,
+            FactoryError::ZeroAllocation => 316,
+            FactoryError::InitialReleaseExceedsAllocation => 317,
+            FactoryError::VestingEndBeforeStart => 318,
+            FactoryError::ZeroVestingInterval => 319,
+            FactoryError::VestingStartInPast => 320,
+            FactoryError::VestingDurationTooLong => 321,
+            FactoryError::TooManyAllocatees => 322,
+            FactoryError::TooManyUnfinishedRegistrations => 323,
+            FactoryError::RegistrationLimitReached => 324,
+
+            FactoryError::StepAlreadyCompleted(_) => 400,
+            FactoryError::AllocationNotInitialized => 401,
+            FactoryError::TokenAlreadyCompacted => 402,
+            FactoryError::AwaitingApproval => 403,
+            FactoryError::NotAwaitingApproval => 404,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            FactoryError::NotInitialized => "The contract is not initialized".to_string(),
+            FactoryError::NotOwner => "Function called not from the contract owner".to_string(),
+            FactoryError::NotCreator => {
+                "Only creator is allowed to execute the function".to_string()
+            }
+            FactoryError::StateNotFound => "Contract state not found".to_string(),
+            FactoryError::AlreadyMigrated => "State is already at the current version".to_string(),
+            FactoryError::UnknownStateVersion => "Unknown contract state version".to_string(),
+            FactoryError::MigrationInProgress => "A data migration is in progress".to_string(),
+            FactoryError::NoMigrationRunning => "No data migration is running".to_string(),
+            FactoryError::InvalidMigrationTarget => "Invalid migration target version".to_string(),
+            FactoryError::OperationPaused(operation) => format!(
+                "Operation {} is paused",
+                near_sdk::serde_json::to_string(operation).unwrap()
+            ),
+            FactoryError::MissingRole(role) => format!(
+                "Function requires the {} role",
+                near_sdk::serde_json::to_string(role).unwrap()
+            ),
+            FactoryError::InvalidOwner => "new_owner is not valid".to_string(),
+            FactoryError::NoPendingOwner => "No owner is proposed".to_string(),
+            FactoryError::NotPendingOwner => {
+                "Function called not from the proposed owner".to_string()
+            }
+            FactoryError::OperationNotFound => "Scheduled operation not found".to_string(),
+            FactoryError::OperationNotReady => {
+                "Scheduled operation is still in its timelock".to_string()
+            }
+            FactoryError::OperationAlreadyStarted => {
+                "Scheduled operation has already started".to_string()
+            }
+            FactoryError::CreatorNotAllowed => {
+                "Creator is not on the registration allowlist".to_string()
+            }
+            FactoryError::TimelockDelayTooShort => {
+                "Timelock delay is shorter than the minimum".to_string()
+            }
+
+            FactoryError::TokenNotRegistered => "Token is not registered".to_string(),
+            FactoryError::TokenAlreadyRegistered => "ft_contract already registered".to_string(),
+            FactoryError::MetadataNotFound => "Not found ft_metadata".to_string(),
+            FactoryError::AllocationNotFound => "Allocation not found".to_string(),
+            FactoryError::EmptyModerationReason => "reason must not be empty".to_string(),
+
+            FactoryError::InsufficientDeposit => {
+                "Attached deposit does not cover the registration cost".to_string()
+            }
+            FactoryError::ZeroTotalSupply => "total_supply must be greater than 0".to_string(),
+            FactoryError::InvalidFtContract => "ft_contract is not valid".to_string(),
+            FactoryError::InvalidFtDeployer => "ft_deployer is not valid".to_string(),
+            FactoryError::AllocationsExceedSupply => {
+                "Total allocations is greater than total supply".to_string()
+            }
+            FactoryError::AllocationsNotFullSupply => "Total allocations is not 100%".to_string(),
+            FactoryError::TreasuryAllocationMissing => "Treasury allocation must exist".to_string(),
+            FactoryError::AllocationBelowClaimed => {
+                "Allocation is smaller than the total claimable".to_string()
+            }
+            FactoryError::VestingIntervalTooLong => {
+                "Vesting interval is larger than vesting time".to_string()
+            }
+            FactoryError::EmptyTokenName => "token_name must not be empty".to_string(),
+            FactoryError::EmptySymbol => "symbol must not be empty".to_string(),
+            FactoryError::InvalidDecimals => "decimals must not be greater than 24".to_string(),
+            FactoryError::InvalidReferenceHash => {
+                "reference_hash must be a 32 bytes hash of reference".to_string()
+            }
+            FactoryError::EmptyIcon => "icon must not be empty".to_string(),
+            FactoryError::SameContractAccounts => {
+                "ft_contract and deployer_contract must be different".to_string()
+            }
+            FactoryError::InvalidAllocationAccount => "Allocation account is not valid".to_string(),
+            FactoryError::ZeroAllocation => "allocated_percent must be greater than 0".to_string(),
+            FactoryError::InitialReleaseExceedsAllocation => {
+                "initial_release must not be greater than allocated_percent".to_string()
+            }
+            FactoryError::VestingEndBeforeStart => {
+                "vesting_end_time must not be before vesting_start_time".to_string()
+            }
+            FactoryError::ZeroVestingInterval => {
+                "vesting_interval must be greater than 0".to_string()
+            }
+            FactoryError::VestingStartInPast => {
+                "vesting_start_time must not be in the past".to_string()
+            }
+            FactoryError::VestingDurationTooLong => format!(
+                "Vesting duration must not exceed {} days",
+                validation::MAX_VESTING_DURATION / 86_400_000_000_000
+            ),
+            FactoryError::TooManyAllocatees => format!(
+                "Number of allocatees must not exceed {}",
+                validation::MAX_ALLOCATEES
+            ),
+            FactoryError::TooManyUnfinishedRegistrations => {
+                "Creator has too many unfinished registrations".to_string()
+            }
+            FactoryError::RegistrationLimitReached => {
+                "Creator reached the registration limit of the window".to_string()
+            }
+
+            FactoryError::StepAlreadyCompleted(step) => format!(
+                "Lifecycle step {} is already completed",
+                near_sdk::serde_json::to_string(step).unwrap()
+            ),
+            FactoryError::AllocationNotInitialized => {
+                "Allocations are not initialized on the deployer".to_string()
+            }
+            FactoryError::TokenAlreadyCompacted => "Token is already compacted".to_string(),
+            FactoryError::AwaitingApproval => "Token is awaiting approval".to_string(),
+            FactoryError::NotAwaitingApproval => "Token is not awaiting approval".to_string(),
+        }
+    }
+
+    pub fn panic(&self) -> ! {
+        env::panic(self.to_string().as_bytes())
+    }
+}
+
+/// Formatted as `E<code>: <message>`, e.g. `E301: total_supply must be greater than 0`
+impl fmt::Display for FactoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "E{}: {}", self.code(), self.message())
+    }
+}
+
+pub(crate) fn require(condition: bool, error: FactoryError) {
+    if !condition {
+        error.panic()
+    }
+}
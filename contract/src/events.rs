@@ -0,0 +1,268 @@
+use crate::config::FactoryConfig;
+use crate::creators::CreatorLimits;
+use crate::moderation::ModerationStatus;
+use crate::pause::PausableOperation;
+use crate::roles::Role;
+use crate::timelock::AdminOperation;
+use crate::*;
+
+pub const EVENT_STANDARD: &str = "token_factory";
+pub const EVENT_STANDARD_VERSION: &str = "1.0.0";
+/// Number of recent changes kept for `get_changes`
+pub const CHANGE_FEED_CAPACITY: u64 = 1000;
+/// Bytes of a moderation reason kept in the change feed, the log has all of it
+pub const MAX_FEED_REASON_LEN: usize = 256;
+
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleStep {
+    FtContractDeployment,
+    DeployerContractDeployment,
+    FtIssuance,
+    AllocationInit,
+}
+
+/// NEP-297 events, logged as `EVENT_JSON:{"standard":"token_factory",...}`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum FactoryEvent {
+    TokenRegistered {
+        id: u64,
+        ft_contract: TokenId,
+        ft_deployer: AccountId,
+        creator: AccountId,
+    },
+    LifecycleStep {
+        ft_contract: TokenId,
+        step: LifecycleStep,
+        success: bool,
+    },
+    // the allocatees are listed by `get_token_allocations`, up to
+    // MAX_ALLOCATEES of them would not fit in the log
+    AllocationInitialized {
+        ft_contract: TokenId,
+        allocatee_count: u64,
+    },
+    TokenUnregistered {
+        ft_contract: TokenId,
+    },
+    MetadataCleared {
+        ft_contract: TokenId,
+    },
+    RegistryReset {
+        token_count: u64,
+    },
+    MigrationStarted {
+        from_version: u32,
+        target_version: u32,
+    },
+    MigrationCompleted {
+        data_version: u32,
+    },
+    TokenCompacted {
+        ft_contract: TokenId,
+        storage_refunded: WrappedBalance,
+    },
+    ConfigChanged {
+        config: FactoryConfig,
+    },
+    // None for every operation
+    Paused {
+        operation: Option<PausableOperation>,
+    },
+    Unpaused {
+        operation: Option<PausableOperation>,
+    },
+    RoleGranted {
+        role: Role,
+        account_id: AccountId,
+    },
+    RoleRevoked {
+        role: Role,
+        account_id: AccountId,
+    },
+    OwnerProposed {
+        owner_id: AccountId,
+        new_owner: AccountId,
+    },
+    OwnerProposalCancelled {
+        new_owner: AccountId,
+    },
+    OwnerChanged {
+        previous_owner: AccountId,
+        owner_id: AccountId,
+    },
+    OperationScheduled {
+        id: u64,
+        operation: AdminOperation,
+        executable_at: WrappedTimestamp,
+    },
+    OperationCancelled {
+        id: u64,
+    },
+    OperationExecuted {
+        id: u64,
+    },
+    TimelockDelayChanged {
+        delay: WrappedDuration,
+    },
+    CurationChanged {
+        curated: bool,
+    },
+    TokenApproved {
+        ft_contract: TokenId,
+    },
+    TokenRejected {
+        ft_contract: TokenId,
+        reason: String,
+        refund: WrappedBalance,
+    },
+    TokenModerated {
+        ft_contract: TokenId,
+        status: ModerationStatus,
+        reason: String,
+    },
+    AllowlistChanged {
+        enabled: bool,
+    },
+    CreatorAllowed {
+        account_id: AccountId,
+    },
+    CreatorDisallowed {
+        account_id: AccountId,
+    },
+    CreatorLimitsChanged {
+        limits: CreatorLimits,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+struct EventLog<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event: &'a FactoryEvent,
+}
+
+impl FactoryEvent {
+    pub fn emit(&self) {
+        let log = EventLog {
+            standard: EVENT_STANDARD,
+            version: EVENT_STANDARD_VERSION,
+            event: self,
+        };
+        env::log(
+            format!(
+                "EVENT_JSON:{}",
+                near_sdk::serde_json::to_string(&log).unwrap()
+            )
+            .as_bytes(),
+        );
+    }
+}
+
+impl FactoryEvent {
+    /// The event as kept in the change feed, where every record has a bounded size
+    fn into_feed_payload(self) -> Self {
+        match self {
+            FactoryEvent::TokenRejected {
+                ft_contract,
+                reason,
+                refund,
+            } => FactoryEvent::TokenRejected {
+                ft_contract,
+                reason: truncate_reason(reason),
+                refund,
+            },
+            FactoryEvent::TokenModerated {
+                ft_contract,
+                status,
+                reason,
+            } => FactoryEvent::TokenModerated {
+                ft_contract,
+                status,
+                reason: truncate_reason(reason),
+            },
+            event => event,
+        }
+    }
+}
+
+fn truncate_reason(mut reason: String) -> String {
+    if reason.len() > MAX_FEED_REASON_LEN {
+        let mut len = MAX_FEED_REASON_LEN;
+        while !reason.is_char_boundary(len) {
+            len -= 1;
+        }
+        reason.truncate(len);
+    }
+    reason
+}
+
+/// A state change kept in the on-chain change feed
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ChangeRecord {
+    pub seq: u64,
+    pub timestamp: WrappedTimestamp,
+    pub block_height: BlockHeight,
+    #[serde(flatten)]
+    pub event: FactoryEvent,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ChangeFeed {
+    pub changes: Vec<ChangeRecord>,
+    // oldest seq still in the feed, a client that is behind it must resync
+    pub oldest_seq: u64,
+    pub next_seq: u64,
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Changes with a seq greater than `since_seq`, oldest first
+    pub fn get_changes(&self, since_seq: Option<u64>, limit: u64) -> ChangeFeed {
+        let oldest_seq = self.next_change_seq.saturating_sub(CHANGE_FEED_CAPACITY);
+        let from = match since_seq {
+            Some(seq) => std::cmp::max(seq.saturating_add(1), oldest_seq),
+            None => oldest_seq,
+        };
+        let to = std::cmp::min(from.saturating_add(limit), self.next_change_seq);
+
+        ChangeFeed {
+            changes: (from..to)
+                .filter_map(|seq| self.changes.get(&(seq % CHANGE_FEED_CAPACITY)))
+                .collect(),
+            oldest_seq,
+            next_seq: self.next_change_seq,
+        }
+    }
+}
+
+impl TokenFactory {
+    /// Logs the event and appends it to the change feed
+    pub(crate) fn record_event(&mut self, event: FactoryEvent) {
+        event.emit();
+
+        let record = self.internal_next_change(event);
+        self.changes
+            .insert(&(record.seq % CHANGE_FEED_CAPACITY), &record);
+        self.next_change_seq += 1;
+    }
+
+    /// The record `record_event` would append for `event`
+    pub(crate) fn internal_next_change(&self, event: FactoryEvent) -> ChangeRecord {
+        ChangeRecord {
+            seq: self.next_change_seq,
+            timestamp: WrappedTimestamp::from(env::block_timestamp()),
+            block_height: env::block_index(),
+            event: event.into_feed_payload(),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Layouts of the registry deployed before versioning, read by `migrate`
+//! and `VersionedState`.
+
+use crate::config::FactoryConfig;
+use crate::pause::PauseState;
+use crate::roles::ROLES;
+use crate::storage::StorageKey;
+use crate::timelock::DEFAULT_TIMELOCK_DELAY;
+use crate::*;
+
+/// Token state of the registry deployed before versioning, stored untagged.
+/// Reads convert it with `baseline_token_id`, data version 1 rewrites it.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldState {
+    pub(crate) ft_contract: AccountId,
+    pub(crate) ft_metadata: Option<FTMetadata>,
+    pub(crate) ft_deployer: AccountId,
+    pub(crate) creator: AccountId,
+    pub(crate) allocations: UnorderedMap<AccountId, TokenAllocation>,
+    pub(crate) ft_contract_deployed: u8,
+    pub(crate) deployer_contract_deployed: u8,
+    pub(crate) ft_issued: u8,
+    pub(crate) allocation_initialized: u8,
+}
+
+/// Id of a baseline token: its index in `tokens`. `migrate` reserves the ids
+/// below the number of baseline tokens, and a baseline token only moves to the
+/// slot of a removed one, so no other token holds its id.
+pub(crate) fn baseline_token_id(
+    tokens: &UnorderedMap<TokenId, VersionedState>,
+    ft_contract: &TokenId,
+) -> u64 {
+    // the map serializes the prefix of its key index first
+    let serialized = tokens.try_to_vec().unwrap();
+    let prefix_len = u32::try_from_slice(&serialized[..4]).unwrap() as usize;
+    let mut index_key = serialized[4..4 + prefix_len].to_vec();
+    index_key.extend(ft_contract.try_to_vec().unwrap());
+    env::storage_read(&index_key)
+        .and_then(|index| u64::try_from_slice(&index).ok())
+        .unwrap_or_else(|| FactoryError::TokenNotRegistered.panic())
+}
+
+impl OldState {
+    /// The state with the numeric id `id`; creation time is unknown for
+    /// tokens registered before ids existed
+    pub fn into_state(self, id: u64) -> State {
+        State {
+            ft_contract: self.ft_contract,
+            ft_metadata: self.ft_metadata,
+            ft_deployer: self.ft_deployer,
+            creator: self.creator,
+            allocations: self.allocations,
+            ft_contract_deployed: self.ft_contract_deployed,
+            deployer_contract_deployed: self.deployer_contract_deployed,
+            ft_issued: self.ft_issued,
+            allocation_initialized: self.allocation_initialized,
+            id,
+            created_at: 0,
+            created_at_height: 0,
+            compacted: None,
+            cost: None,
+        }
+    }
+}
+
+/// Layout of the registry deployed before the state was versioned
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TokenFactoryV0 {
+    pub(crate) owner_id: AccountId,
+    pub(crate) admins: UnorderedSet<AccountId>,
+    pub(crate) tokens: UnorderedMap<TokenId, VersionedState>,
+    pub(crate) user_token_map: LookupMap<AccountId, UnorderedSet<TokenId>>,
+}
+
+/// Allocatee index of the registry deployed before versioning, data version 3
+/// replaces it with `user_token_map`
+pub(crate) fn baseline_user_token_map() -> LookupMap<AccountId, UnorderedSet<TokenId>> {
+    LookupMap::new(b"tokenmap".to_vec())
+}
+
+/// Grants every role to the admins, who could call every admin method and
+/// reserves the ids of the baseline tokens. The indexes of the tokens are
+/// backfilled by `run_migration`.
+impl From<TokenFactoryV0> for TokenFactory {
+    fn from(mut state: TokenFactoryV0) -> Self {
+        let mut roles = LookupMap::new(StorageKey::Roles);
+        for role in ROLES.iter() {
+            let mut members = UnorderedSet::new(StorageKey::RoleMembers { role: *role });
+            members.extend(state.admins.iter());
+            if !members.is_empty() {
+                roles.insert(role, &members);
+            }
+        }
+        state.admins.clear();
+
+        TokenFactory {
+            owner_id: state.owner_id,
+            pending_owner: None,
+            roles,
+            next_token_id: state.tokens.len(),
+            tokens: state.tokens,
+            user_token_map: LookupMap::new(StorageKey::UserTokenMap),
+            creator_token_map: LookupMap::new(StorageKey::CreatorTokenMap),
+            token_order: TreeMap::new(StorageKey::TokenOrder),
+            next_change_seq: 0,
+            changes: LookupMap::new(StorageKey::Changes),
+            data_version: 0,
+            migration: None,
+            config: FactoryConfig::default(),
+            paused: PauseState::default(),
+            timelock_delay: DEFAULT_TIMELOCK_DELAY,
+            next_operation_id: 0,
+            scheduled_operations: UnorderedMap::new(StorageKey::ScheduledOperations),
+            curated: false,
+            review_queue: UnorderedMap::new(StorageKey::ReviewQueue),
+            moderation: LookupMap::new(StorageKey::Moderation),
+            allowlist_enabled: false,
+            allowed_creators: UnorderedSet::new(StorageKey::AllowedCreators),
+            creator_limits: CreatorLimits::default(),
+            creator_usage: LookupMap::new(StorageKey::CreatorUsage),
+            compacted_allocatees: LookupMap::new(StorageKey::CompactedAllocatees),
+        }
+    }
+}
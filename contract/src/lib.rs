@@ -5,19 +5,51 @@ Functions:
 
 // To conserve gas, efficient serialization is achieved through Borsh (http://borsh.io/)
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::{Base64VecU8, WrappedBalance, WrappedDuration, WrappedTimestamp};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::serde_json::{json, Value};
 use near_sdk::{env, near_bindgen, PanicOnDefault};
-use near_sdk::{AccountId, Balance, Duration, Gas, Timestamp};
+use near_sdk::{AccountId, Balance, BlockHeight, Duration, Gas, StorageUsage, Timestamp};
 use near_sdk::{Promise, PromiseResult};
 use std::collections::HashMap;
 
 use crate::callbacks::ext_self;
+use crate::compaction::{CompactSummary, WrappedCompactSummary};
+use crate::config::FactoryConfig;
+use crate::costs::TokenCost;
+use crate::creators::{CreatorLimits, CreatorUsage};
+use crate::errors::{require, FactoryError};
+use crate::events::{ChangeRecord, FactoryEvent};
+use crate::legacy::OldState;
+use crate::migration::MigrationJob;
+use crate::moderation::{ModerationRecord, ModerationStatus};
+use crate::pause::{PausableOperation, PauseState};
+use crate::review::ReviewRequest;
+use crate::roles::Role;
+use crate::storage::StorageKey;
+use crate::timelock::{ScheduledOperation, DEFAULT_TIMELOCK_DELAY};
+use crate::validation::{validate_allocation, RegistrationInput};
 
 mod admins;
 mod callbacks;
+mod compaction;
+mod config;
+mod costs;
+mod creators;
+mod errors;
+mod events;
+mod legacy;
+mod migration;
+mod moderation;
+mod ownership;
+mod pause;
+mod portfolio;
+mod review;
+mod roles;
+mod storage;
+mod timelock;
+mod validation;
 mod views;
 
 near_sdk::setup_alloc!();
@@ -52,6 +84,31 @@ pub struct TokenAllocation {
     claimed: u64,
 }
 
+impl From<WrappedTokenAllocation> for TokenAllocation {
+    fn from(allocation: WrappedTokenAllocation) -> Self {
+        TokenAllocation {
+            allocated_percent: allocation.allocated_percent,
+            initial_release: allocation.initial_release,
+            vesting_start_time: allocation.vesting_start_time.into(),
+            vesting_end_time: allocation.vesting_end_time.into(),
+            vesting_interval: allocation.vesting_interval.into(),
+            claimed: 0,
+        }
+    }
+}
+
+impl From<TokenAllocation> for WrappedTokenAllocation {
+    fn from(allocation: TokenAllocation) -> Self {
+        WrappedTokenAllocation {
+            allocated_percent: allocation.allocated_percent,
+            initial_release: allocation.initial_release,
+            vesting_start_time: WrappedTimestamp::from(allocation.vesting_start_time),
+            vesting_end_time: WrappedTimestamp::from(allocation.vesting_end_time),
+            vesting_interval: WrappedDuration::from(allocation.vesting_interval),
+        }
+    }
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Clone, Deserialize, Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct FTMetadata {
@@ -90,6 +147,16 @@ impl From<FTMetadata> for WrappedFTMetadata {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TokenStatus {
+    Registered,
+    Deploying,
+    Deployed,
+    Issued,
+    AllocationInitialized,
+}
+
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct State {
     // token info
@@ -108,11 +175,74 @@ pub struct State {
     deployer_contract_deployed: u8,
     ft_issued: u8,
     allocation_initialized: u8,
+
+    // registration
+    id: u64,
+    created_at: Timestamp,
+    created_at_height: BlockHeight,
+
+    // Some once the allocations are dropped in favor of the deployer
+    compacted: Option<CompactSummary>,
+
+    // what the creator paid, None for tokens registered before it was recorded
+    cost: Option<TokenCost>,
+}
+
+/// Token record as stored in `tokens`. Reads upgrade baseline records to the
+/// current `State`, writes always store the latest version.
+// decoded one record at a time, boxing would only add an allocation per read
+#[allow(clippy::large_enum_variant)]
+pub enum VersionedState {
+    V0(OldState),
+    V1(State),
+}
+
+// Baseline records have no tag. They start with the u32 length of `ft_contract`
+// (2..=64), so a leading marker byte can never be mistaken for one.
+const VERSIONED_STATE_MARKER: u8 = 0xff;
+
+impl BorshSerialize for VersionedState {
+    fn serialize<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self {
+            VersionedState::V0(state) => state.serialize(writer),
+            VersionedState::V1(state) => {
+                writer.write_all(&[VERSIONED_STATE_MARKER, 1])?;
+                state.serialize(writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for VersionedState {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        if buf.first() != Some(&VERSIONED_STATE_MARKER) {
+            return Ok(VersionedState::V0(OldState::deserialize(buf)?));
+        }
+        let version = buf.get(1).copied();
+        *buf = &buf[std::cmp::min(2, buf.len())..];
+        match version {
+            Some(1) => Ok(VersionedState::V1(State::deserialize(buf)?)),
+            version => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Unknown token state version {:?}", version),
+            )),
+        }
+    }
+}
+
+impl From<State> for VersionedState {
+    fn from(state: State) -> Self {
+        VersionedState::V1(state)
+    }
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Clone, Deserialize, Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct WrappedState {
+    id: u64,
+    created_at: WrappedTimestamp,
+    created_at_height: BlockHeight,
+
     // token info
     ft_contract: AccountId,
     ft_metadata: Option<WrappedFTMetadata>,
@@ -129,16 +259,150 @@ pub struct WrappedState {
     deployer_contract_deployed: u8,
     ft_issued: u8,
     allocation_initialized: u8,
+
+    // Some once compacted, `ft_metadata` and `allocations` are then empty
+    // and only live on the FT and deployer contracts
+    compacted: Option<WrappedCompactSummary>,
+    cost: Option<TokenCost>,
+}
+
+impl State {
+    pub fn status(&self) -> TokenStatus {
+        if self.allocation_initialized == 1 {
+            TokenStatus::AllocationInitialized
+        } else if self.ft_issued == 1 {
+            TokenStatus::Issued
+        } else if self.ft_contract_deployed == 1 && self.deployer_contract_deployed == 1 {
+            TokenStatus::Deployed
+        } else if self.ft_contract_deployed == 1 || self.deployer_contract_deployed == 1 {
+            TokenStatus::Deploying
+        } else {
+            TokenStatus::Registered
+        }
+    }
+
+    /// Amount in base units for the given percent (Decimal: 2) of total supply
+    pub fn amount_of(&self, percent: u64) -> Balance {
+        let (percent, max) = (percent as Balance, MAX_SUPPLY_PERCENT as Balance);
+        // split so that supplies close to u128::MAX do not overflow
+        self.total_supply()
+            .map(|total_supply| total_supply / max * percent + total_supply % max * percent / max)
+            .unwrap_or(0)
+    }
+
+    // metadata, read from the compact summary once the token is compacted
+
+    pub fn symbol(&self) -> Option<String> {
+        match (&self.ft_metadata, &self.compacted) {
+            (Some(metadata), _) => Some(metadata.symbol.clone()),
+            (None, Some(summary)) => Some(summary.symbol.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn token_name(&self) -> Option<String> {
+        match (&self.ft_metadata, &self.compacted) {
+            (Some(metadata), _) => Some(metadata.token_name.clone()),
+            (None, Some(summary)) => Some(summary.token_name.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn decimals(&self) -> Option<u8> {
+        match (&self.ft_metadata, &self.compacted) {
+            (Some(metadata), _) => Some(metadata.decimals),
+            (None, Some(summary)) => Some(summary.decimals),
+            _ => None,
+        }
+    }
+
+    pub fn total_supply(&self) -> Option<Balance> {
+        match (&self.ft_metadata, &self.compacted) {
+            (Some(metadata), _) => Some(metadata.total_supply),
+            (None, Some(summary)) => Some(summary.total_supply),
+            _ => None,
+        }
+    }
+
+    /// Number of allocations, also after they are compacted
+    pub fn allocation_count(&self) -> u64 {
+        self.allocations.len()
+            + self
+                .compacted
+                .as_ref()
+                .map(|summary| summary.allocation_count)
+                .unwrap_or(0)
+    }
+}
+
+/// Compact token info for list views, without the allocations.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenSummary {
+    id: u64,
+    ft_contract: TokenId,
+    symbol: Option<String>,
+    name: Option<String>,
+    decimals: Option<u8>,
+    total_supply: Option<WrappedBalance>,
+    creator: AccountId,
+    status: TokenStatus,
+    created_at: WrappedTimestamp,
+    allocation_count: u64,
+    // true once the allocations only live on the deployer
+    compacted: bool,
+    moderation_status: ModerationStatus,
+}
+
+impl TokenSummary {
+    pub fn new(state: State, moderation_status: ModerationStatus) -> Self {
+        TokenSummary {
+            id: state.id,
+            symbol: state.symbol(),
+            name: state.token_name(),
+            decimals: state.decimals(),
+            total_supply: state.total_supply().map(WrappedBalance::from),
+            status: state.status(),
+            created_at: WrappedTimestamp::from(state.created_at),
+            allocation_count: state.allocation_count(),
+            compacted: state.compacted.is_some(),
+            moderation_status,
+            creator: state.creator,
+            ft_contract: state.ft_contract,
+        }
+    }
+}
+
+/// One page of a cursor-based listing. `next_cursor` is the token id to pass
+/// as `from_id` for the next page, None when there are no more items.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Page<T> {
+    items: Vec<T>,
+    next_cursor: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountAllocation {
+    ft_contract: TokenId,
+    symbol: Option<String>,
+    decimals: Option<u8>,
+    status: TokenStatus,
+    // None once the token is compacted, the deployer then holds the allocation
+    allocated_amount: Option<WrappedBalance>,
+    allocation: Option<WrappedTokenAllocation>,
 }
 
 impl From<State> for WrappedState {
     fn from(state: State) -> Self {
         WrappedState {
+            id: state.id,
+            created_at: WrappedTimestamp::from(state.created_at),
+            created_at_height: state.created_at_height,
+
             ft_contract: state.ft_contract,
-            ft_metadata: match state.ft_metadata {
-                None => None,
-                Some(d) => Some(WrappedFTMetadata::from(d)),
-            },
+            ft_metadata: state.ft_metadata.map(WrappedFTMetadata::from),
             // Some(WrappedFTMetadata::from(state.ft_metadata.expect("ft metadata not found!"))),
 
             // creator and deployer
@@ -153,68 +417,100 @@ impl From<State> for WrappedState {
             deployer_contract_deployed: state.deployer_contract_deployed,
             ft_issued: state.ft_issued,
             allocation_initialized: state.allocation_initialized,
-        }
-    }
-}
 
-impl Default for State {
-    fn default() -> Self {
-        let default_string_value = String::from("__default_value__");
-        Self {
-            ft_contract: default_string_value.clone(),
-            ft_metadata: Some(FTMetadata {
-                total_supply: 0,
-                token_name: default_string_value.clone(),
-                symbol: default_string_value.clone(),
-                icon: Some(default_string_value.clone()),
-                reference: None,
-                reference_hash: None,
-                decimals: 0,
-            }),
-
-            ft_deployer: default_string_value.clone(),
-            creator: default_string_value.clone(),
-
-            allocations: UnorderedMap::new(b"tokennomics".to_vec()),
-
-            ft_contract_deployed: 0,
-            deployer_contract_deployed: 0,
-            ft_issued: 0,
-            allocation_initialized: 0,
+            compacted: state.compacted.map(WrappedCompactSummary::from),
+            cost: state.cost,
         }
     }
 }
-//TODO: Delete this struct
-#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
-pub struct OldTokenFactory {
-    owner_id: AccountId,
-    admins: UnorderedSet<AccountId>,
-    tokens: UnorderedMap<TokenId, State>,
-    user_token_map: LookupMap<AccountId, UnorderedSet<TokenId>>,
-}
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct TokenFactory {
     owner_id: AccountId,
-    admins: UnorderedSet<AccountId>,
-    tokens: UnorderedMap<TokenId, State>,
-    user_token_map: LookupMap<AccountId, UnorderedSet<TokenId>>,
+    // proposed by the owner, becomes the owner once it accepts
+    pending_owner: Option<AccountId>,
+    roles: LookupMap<Role, UnorderedSet<AccountId>>,
+    tokens: UnorderedMap<TokenId, VersionedState>,
+    // tokens of each allocatee and creator by numeric id, pages read only the
+    // tokens they return
+    user_token_map: LookupMap<AccountId, TreeMap<u64, TokenId>>,
+    creator_token_map: LookupMap<AccountId, TreeMap<u64, TokenId>>,
+
+    // numeric token ids in creation order, used as a stable pagination cursor
+    next_token_id: u64,
+    token_order: TreeMap<u64, TokenId>,
+
+    // ring buffer of the latest changes, keyed by seq % CHANGE_FEED_CAPACITY
+    next_change_seq: u64,
+    changes: LookupMap<u64, ChangeRecord>,
+
+    // version the token data is fully migrated to, see `run_migration`
+    data_version: u32,
+    migration: Option<MigrationJob>,
+
+    config: FactoryConfig,
+    paused: PauseState,
+
+    // destructive admin operations wait `timelock_delay` between scheduling and execution
+    timelock_delay: Duration,
+    next_operation_id: u64,
+    scheduled_operations: UnorderedMap<u64, ScheduledOperation>,
+
+    // while curated, registrations wait in `review_queue` until a moderator approves them
+    curated: bool,
+    review_queue: UnorderedMap<TokenId, ReviewRequest>,
+
+    // moderation records of each token, the last one is its current status
+    moderation: LookupMap<TokenId, Vector<ModerationRecord>>,
+
+    // while the allowlist is enabled only `allowed_creators` can register
+    allowlist_enabled: bool,
+    allowed_creators: UnorderedSet<AccountId>,
+    creator_limits: CreatorLimits,
+    creator_usage: LookupMap<AccountId, CreatorUsage>,
+
+    // allocatees of the allocations compaction dropped, their `user_token_map`
+    // entries are deleted with the token
+    compacted_allocatees: LookupMap<TokenId, Vector<AccountId>>,
 }
 
 #[near_bindgen]
 impl TokenFactory {
     #[init]
     pub fn new(owner_id: AccountId) -> Self {
-        return Self {
-            owner_id: String::from(owner_id),
-            admins: UnorderedSet::new(b"admins".to_vec()),
-            tokens: UnorderedMap::new(b"tokenspec".to_vec()),
-            user_token_map: LookupMap::new(b"tokenmap".to_vec()),
-        };
+        admins::write_state_version(admins::STATE_VERSION);
+        Self {
+            owner_id,
+            pending_owner: None,
+            roles: LookupMap::new(StorageKey::Roles),
+            tokens: UnorderedMap::new(StorageKey::Tokens),
+            user_token_map: LookupMap::new(StorageKey::UserTokenMap),
+            creator_token_map: LookupMap::new(StorageKey::CreatorTokenMap),
+            next_token_id: 0,
+            token_order: TreeMap::new(StorageKey::TokenOrder),
+            next_change_seq: 0,
+            changes: LookupMap::new(StorageKey::Changes),
+            data_version: migration::DATA_VERSION,
+            migration: None,
+            config: FactoryConfig::default(),
+            paused: PauseState::default(),
+            timelock_delay: DEFAULT_TIMELOCK_DELAY,
+            next_operation_id: 0,
+            scheduled_operations: UnorderedMap::new(StorageKey::ScheduledOperations),
+            curated: false,
+            review_queue: UnorderedMap::new(StorageKey::ReviewQueue),
+            moderation: LookupMap::new(StorageKey::Moderation),
+            allowlist_enabled: false,
+            allowed_creators: UnorderedSet::new(StorageKey::AllowedCreators),
+            creator_limits: CreatorLimits::default(),
+            creator_usage: LookupMap::new(StorageKey::CreatorUsage),
+            compacted_allocatees: LookupMap::new(StorageKey::CompactedAllocatees),
+        }
     }
 
     #[payable]
+    #[allow(clippy::too_many_arguments)]
     pub fn register(
         &mut self,
         ft_contract: AccountId,
@@ -228,154 +524,120 @@ impl TokenFactory {
         allocations: TokenAllocationInput,
         decimals: u8,
     ) {
-        assert!(
-            env::attached_deposit() >= 4_000_000_000_000_000_000_000_000,
-            "Minimum deposit is 4 NEAR",
-        );
-
-        let mut allocation_prefix = Vec::with_capacity(33);
-        // Adding unique prefix.
-        allocation_prefix.push(b'a');
-        // Adding the hash of the account_id (key of the outer map) to the prefix.
-        // This is needed to differentiate across accounts.
-        allocation_prefix.extend(env::sha256(
-            format!("{}@{}", ft_contract, env::block_timestamp()).as_bytes(),
-        ));
-
-        let mut state_allocations: UnorderedMap<AccountId, TokenAllocation> =
-            UnorderedMap::new(allocation_prefix);
-
-        let mut treasury_exist = false;
-
-        for (account_id, alloc) in &allocations {
-            let a = TokenAllocation {
-                allocated_percent: alloc.allocated_percent.into(),
-                initial_release: alloc.initial_release,
-                vesting_start_time: alloc.vesting_start_time.into(),
-                vesting_end_time: alloc.vesting_end_time.into(),
-                vesting_interval: alloc.vesting_interval.into(),
-                claimed: 0,
-            };
-
-            if account_id == TOKENHUB_TREASURY && a.allocated_percent > 0 {
-                treasury_exist = true;
-            }
-
-            self.assert_invalid_allocation(a.clone());
-            state_allocations.insert(account_id, &a);
-
-            let total_allocs: u64 = state_allocations
-                .values()
-                .map(|v: TokenAllocation| v.allocated_percent)
-                .sum();
-
-            assert!(
-                total_allocs <= MAX_SUPPLY_PERCENT,
-                "Total allocations is greater than total supply"
-            );
+        let input = RegistrationInput {
+            ft_contract,
+            deployer_contract,
+            total_supply: total_supply.into(),
+            token_name,
+            symbol,
+            icon,
+            reference,
+            reference_hash,
+            allocations,
+            decimals,
+        };
+        self.assert_not_paused(PausableOperation::Register);
+        let issues = self.validate_registration_input(&input);
+        if !issues.is_empty() {
+            let messages: Vec<String> = issues
+                .iter()
+                .map(|(error, account_id)| match account_id {
+                    Some(account_id) => format!("{} ({})", error, account_id),
+                    None => error.to_string(),
+                })
+                .collect();
+            env::panic(messages.join("; ").as_bytes());
         }
+        let creator = env::signer_account_id();
+        let usage = self.internal_check_creator(&creator);
 
-        let total_allocs: u64 = state_allocations
-            .values()
-            .map(|v: TokenAllocation| v.allocated_percent)
-            .sum();
-        assert!(
-            total_allocs == MAX_SUPPLY_PERCENT,
-            "Total allocations is not 100%"
-        );
+        let storage_before = env::storage_usage();
+        self.creator_usage.insert(&creator, &usage);
+        let mut token = self.internal_new_token(&input, creator);
+        for (account_id, alloc) in input.allocations {
+            token
+                .allocations
+                .insert(&account_id, &TokenAllocation::from(alloc));
+        }
 
-        assert!(treasury_exist, "Treasury allocation must exist!");
+        let ft_contract = input.ft_contract;
+        self.internal_add_creator_token(token.creator.clone(), token.id, ft_contract.clone());
+        self.next_token_id += 1;
+        self.token_order.insert(&token.id, &ft_contract);
 
-        let token = State {
+        let event = FactoryEvent::TokenRegistered {
+            id: token.id,
             ft_contract: ft_contract.clone(),
-            ft_metadata: Some(FTMetadata {
-                total_supply: total_supply.into(),
-                token_name,
-                symbol,
-                icon,
-                reference,
-                reference_hash,
-                decimals,
-            }),
-
-            ft_deployer: deployer_contract,
-            creator: env::signer_account_id(),
-
-            allocations: state_allocations,
-
-            ft_contract_deployed: 0,
-            deployer_contract_deployed: 0,
-            ft_issued: 0,
-            allocation_initialized: 0,
+            ft_deployer: token.ft_deployer.clone(),
+            creator: token.creator.clone(),
         };
-
-        assert!(
-            token
-                .ft_metadata
-                .as_ref()
-                .expect("Not found ft_metadata")
-                .total_supply
-                > 0,
-            "total_supply must be greater than 0",
-        );
-        assert!(
-            env::is_valid_account_id(token.ft_contract.as_bytes()),
-            "ft_contract is not valid",
-        );
-        assert!(
-            env::is_valid_account_id(token.ft_deployer.as_bytes()),
-            "ft_deployer is not valid",
-        );
-        if let Some(_) = self.tokens.get(&token.ft_contract) {
-            std::panic!("ft_contract already registered");
+        if self.curated {
+            self.review_queue
+                .insert(&ft_contract, &ReviewRequest::new(token.creator.clone()));
         }
-
-        // TODO: validate more?
-        self.tokens.insert(&ft_contract, &token);
+        self.internal_set_token(token);
+        self.record_event(event);
+
+        // the cost is stored in place of the placeholder, the size does not change
+        let cost = self.internal_charge(
+            storage_before,
+            self.config.account_funding(),
+            self.config.registration_fee.into(),
+        );
+        let mut token = self.internal_get_token(&ft_contract);
+        token.cost = Some(cost);
+        self.internal_set_token(token);
     }
 
     pub fn create_ft_contract(&mut self, ft_contract: AccountId) -> Promise {
-        let token = self.tokens.get(&ft_contract.clone()).unwrap_or_default();
+        self.assert_not_paused(PausableOperation::FtContractDeployment);
+        let token = self.internal_get_token(&ft_contract);
         self.assert_creator(token.creator);
+        self.assert_approved(&ft_contract);
 
-        return Promise::new(ft_contract.parse().unwrap())
+        Promise::new(ft_contract.parse().unwrap())
             .create_account()
             //Disable this line in production
             .add_full_access_key(env::signer_account_pk())
-            .transfer(4_000_000_000_000_000_000_000_000)
+            .transfer(self.config.ft_account_funding.into())
             .deploy_contract(FT_WASM_CODE.to_vec())
             .then(ext_self::on_ft_contract_deployed(
                 ft_contract,
                 &env::current_account_id(),
                 0,
                 DEFAULT_GAS_FEE,
-            ));
+            ))
     }
 
     pub fn create_deployer_contract(&mut self, ft_contract: AccountId) -> Promise {
-        let token = self.tokens.get(&ft_contract).unwrap_or_default();
+        self.assert_not_paused(PausableOperation::DeployerContractDeployment);
+        let token = self.internal_get_token(&ft_contract);
         self.assert_creator(token.creator);
+        self.assert_approved(&ft_contract);
 
-        return Promise::new(token.ft_deployer.parse().unwrap())
+        Promise::new(token.ft_deployer.parse().unwrap())
             .create_account()
             // .add_full_access_key(env::signer_account_pk())
-            .transfer(4_000_000_000_000_000_000_000_000)
+            .transfer(self.config.deployer_account_funding.into())
             .deploy_contract(DEPLOYER_WASM_CODE.to_vec())
             .then(ext_self::on_ft_deployer_deployed(
                 ft_contract,
                 &env::current_account_id(),
                 0,
                 DEFAULT_GAS_FEE,
-            ));
+            ))
     }
 
     pub fn issue_ft(&mut self, ft_contract: AccountId) -> Promise {
-        let token = self.tokens.get(&ft_contract).unwrap_or_default();
+        self.assert_not_paused(PausableOperation::FtIssuance);
+        let token = self.internal_get_token(&ft_contract);
         self.assert_creator(token.creator);
 
-        let ft_metadata = token.ft_metadata.expect("Not found ft_metadata");
+        let ft_metadata = token
+            .ft_metadata
+            .unwrap_or_else(|| FactoryError::MetadataNotFound.panic());
 
-        return Promise::new(ft_contract.parse().unwrap())
+        Promise::new(ft_contract.parse().unwrap())
             .function_call(
                 b"new".to_vec(),
                 json!({
@@ -405,46 +667,53 @@ impl TokenFactory {
                 &env::current_account_id(),
                 0,
                 DEFAULT_GAS_FEE,
-            ));
+            ))
     }
 
+    /// Charges the creator for the allocatee index entries it adds
+    #[payable]
     pub fn init_token_allocation(&mut self, ft_contract: AccountId) -> Promise {
-        let token = self.tokens.get(&ft_contract).unwrap_or_default();
-        self.assert_creator(token.creator);
+        self.assert_not_paused(PausableOperation::AllocationInit);
+        let mut token = self.internal_get_token(&ft_contract);
+        self.assert_creator(token.creator.clone());
+        let storage_before = env::storage_usage();
 
         let mut allocations: HashMap<AccountId, WrappedTokenAllocation> = HashMap::new();
 
         for k in token.allocations.keys() {
             //Add allocators to user_tokens_map
-            self.internal_add_user_token(k.clone(), ft_contract.clone());
+            self.internal_add_user_token(k.clone(), token.id, ft_contract.clone());
 
             allocations.insert(
                 k.clone(),
                 token
                     .allocations
-                    .get(&k.clone())
-                    .map(|v| WrappedTokenAllocation {
-                        allocated_percent: v.allocated_percent,
-                        initial_release: v.initial_release,
-                        vesting_start_time: WrappedTimestamp::from(v.vesting_start_time),
-                        vesting_end_time: WrappedTimestamp::from(v.vesting_end_time),
-                        vesting_interval: WrappedTimestamp::from(v.vesting_interval),
-                    })
-                    .expect("Allocation not found"),
+                    .get(&k)
+                    .map(WrappedTokenAllocation::from)
+                    .unwrap_or_else(|| FactoryError::AllocationNotFound.panic()),
             );
         }
 
-        return Promise::new(token.ft_deployer.parse().unwrap())
+        let total_supply = token
+            .ft_metadata
+            .as_ref()
+            .unwrap_or_else(|| FactoryError::MetadataNotFound.panic())
+            .total_supply;
+
+        let cost = self.internal_charge(storage_before, 0, 0);
+        let ft_deployer = token.ft_deployer.clone();
+        token.cost = Some(match &token.cost {
+            Some(paid) => paid.add(&cost),
+            None => cost,
+        });
+        self.internal_set_token(token);
+
+        Promise::new(ft_deployer.parse().unwrap())
             .function_call(
                 b"new".to_vec(),
                 json!({
                     "ft_contract_name": ft_contract,
-                    "total_supply": WrappedBalance::from(
-                        token
-                            .ft_metadata
-                            .expect("Not found ft_metadata")
-                            .total_supply
-                    ),
+                    "total_supply": WrappedBalance::from(total_supply),
                     "allocations": allocations
                 })
                 .to_string()
@@ -458,66 +727,107 @@ impl TokenFactory {
                 &env::current_account_id(),
                 0,
                 DEFAULT_GAS_FEE,
-            ));
+            ))
     }
 
     /// Utils
     //Get total allocations
     pub fn assert_invalid_allocations(&self, ft_contract: AccountId) {
-        let token = self.tokens.get(&ft_contract).unwrap_or_default();
+        let token = self.internal_get_token(&ft_contract);
 
-        env::log(
-            format!(
-                "total supply: {}, allocation length: {}",
-                token.ft_metadata.as_ref().expect("fadfa").total_supply,
-                token.allocations.values_as_vector().len(),
-            )
-            .as_bytes(),
-        );
-
-        assert!(
+        require(
             token
                 .ft_metadata
                 .as_ref()
-                .expect("Not found ft_metadata")
+                .unwrap_or_else(|| FactoryError::MetadataNotFound.panic())
                 .total_supply
                 > 0
-                && token.allocations.values_as_vector().len() > 0,
-            "Token is not register"
+                && !token.allocations.values_as_vector().is_empty(),
+            FactoryError::TokenNotRegistered,
         );
 
         let total_allocations: u64 = token
             .allocations
             .values()
             .map(|a| {
-                self.assert_invalid_allocation(a.clone());
+                if let Some(error) = validate_allocation(&a, None).first() {
+                    error.panic();
+                }
                 a.allocated_percent
             })
             .sum();
 
-        assert!(
+        require(
             total_allocations == MAX_SUPPLY_PERCENT,
-            "Total allocations is not equal to total supply"
+            FactoryError::AllocationsNotFullSupply,
         );
     }
 
-    fn assert_invalid_allocation(&self, allocation: TokenAllocation) {
-        //TODO: Allocation > 0
-        assert!(
-            allocation.allocated_percent >= allocation.claimed,
-            "Allocation is smaller than the total claimable",
-        );
-        assert!(
-            allocation.vesting_interval
-                <= allocation.vesting_end_time - allocation.vesting_start_time,
-            "Vesting interval is larger than vesting time",
-        );
+    /// State of a new registration, without allocations. The cost is a
+    /// placeholder as large as the one `register` stores.
+    pub(crate) fn internal_new_token(
+        &self,
+        input: &RegistrationInput,
+        creator: AccountId,
+    ) -> State {
+        State {
+            ft_contract: input.ft_contract.clone(),
+            ft_metadata: Some(FTMetadata {
+                total_supply: input.total_supply,
+                token_name: input.token_name.clone(),
+                symbol: input.symbol.clone(),
+                icon: input.icon.clone(),
+                reference: input.reference.clone(),
+                reference_hash: input.reference_hash.clone(),
+                decimals: input.decimals,
+            }),
+
+            ft_deployer: input.deployer_contract.clone(),
+            creator,
+
+            allocations: UnorderedMap::new(StorageKey::allocations(
+                self.next_token_id,
+                &input.ft_contract,
+            )),
+
+            ft_contract_deployed: 0,
+            deployer_contract_deployed: 0,
+            ft_issued: 0,
+            allocation_initialized: 0,
+
+            id: self.next_token_id,
+            created_at: env::block_timestamp(),
+            created_at_height: env::block_index(),
+            compacted: None,
+            cost: Some(TokenCost::new(0, 0, 0)),
+        }
+    }
+
+    pub(crate) fn internal_find_token(&self, ft_contract: &TokenId) -> Option<State> {
+        self.tokens.get(ft_contract).map(|state| match state {
+            VersionedState::V0(state) => {
+                state.into_state(legacy::baseline_token_id(&self.tokens, ft_contract))
+            }
+            VersionedState::V1(state) => state,
+        })
+    }
+
+    pub(crate) fn internal_get_token(&self, ft_contract: &TokenId) -> State {
+        self.internal_find_token(ft_contract)
+            .unwrap_or_else(|| FactoryError::TokenNotRegistered.panic())
+    }
+
+    /// Stores the token as the latest version
+    pub(crate) fn internal_set_token(&mut self, token: State) {
+        let ft_contract = token.ft_contract.clone();
+        self.tokens
+            .insert(&ft_contract, &VersionedState::from(token));
     }
 
     fn assert_creator(&self, creator: AccountId) {
-        assert!(
+        require(
             env::predecessor_account_id() == creator,
-            "Only creator is allowed to execute the function",
+            FactoryError::NotCreator,
         );
     }
 }
@@ -537,8 +847,12 @@ impl TokenFactory {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::timelock::{AdminOperation, MIN_TIMELOCK_DELAY};
+    use near_sdk::test_utils::get_logs;
     use near_sdk::MockedBlockchain;
-    use near_sdk::{testing_env, VMContext};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, VMContext};
+
+    const FT_CONTRACT: &str = "test001.tokenhub.testnet";
 
     // mock the context for testing, notice "signer_account_id" that was accessed above from env::
     fn get_context(input: Vec<u8>, is_view: bool) -> VMContext {
@@ -550,10 +864,10 @@ mod tests {
             input,
             block_index: 0,
             block_timestamp: 0,
-            account_balance: 0,
+            account_balance: 1_000_000_000_000_000_000_000_000_000,
             account_locked_balance: 0,
             storage_usage: 0,
-            attached_deposit: 1_000_000_000_000_000_000_000_000,
+            attached_deposit: 10_000_000_000_000_000_000_000_000,
             prepaid_gas: 10u64.pow(18),
             random_seed: vec![0, 1, 2],
             is_view,
@@ -561,4 +875,1484 @@ mod tests {
             epoch_height: 19,
         }
     }
+
+    fn setup_contract() -> TokenFactory {
+        testing_env!(get_context(vec![], false));
+        let contract = TokenFactory::new("owner_near".to_string());
+        // views assert that the contract state exists
+        env::state_write(&contract);
+        contract
+    }
+
+    // keeps the storage usage of the previous context so storage writes stay consistent
+    fn set_promise_results(promise_results: Vec<PromiseResult>) {
+        let mut context = get_context(vec![], false);
+        context.storage_usage = env::storage_usage();
+        testing_env!(
+            context,
+            VMConfig::default(),
+            RuntimeFeesConfig::default(),
+            Default::default(),
+            promise_results
+        );
+    }
+
+    fn set_predecessor(account_id: &str) {
+        let mut context = get_context(vec![], false);
+        context.predecessor_account_id = account_id.to_string();
+        context.storage_usage = env::storage_usage();
+        testing_env!(context);
+    }
+
+    // schedules `operation` as `account_id` and moves the clock past the timelock
+    fn schedule_and_wait(
+        contract: &mut TokenFactory,
+        account_id: &str,
+        operation: AdminOperation,
+    ) -> u64 {
+        set_predecessor(account_id);
+        let id = contract.schedule_operation(operation);
+        let mut context = get_context(vec![], false);
+        context.predecessor_account_id = account_id.to_string();
+        context.storage_usage = env::storage_usage();
+        context.block_timestamp = env::block_timestamp() + contract.timelock_delay;
+        testing_env!(context);
+        id
+    }
+
+    fn allocation(allocated_percent: u64) -> WrappedTokenAllocation {
+        WrappedTokenAllocation {
+            allocated_percent,
+            initial_release: 0,
+            vesting_start_time: WrappedTimestamp::from(1_000_000_000_000),
+            vesting_end_time: WrappedTimestamp::from(1_000_000_000_000 + 10 * 86_400_000_000_000),
+            vesting_interval: WrappedDuration::from(86_400_000_000_000),
+        }
+    }
+
+    fn token_allocations() -> TokenAllocationInput {
+        let mut allocations: TokenAllocationInput = HashMap::new();
+        allocations.insert(TOKENHUB_TREASURY.to_string(), allocation(800));
+        allocations.insert("harrynguyen_near".to_string(), allocation(9200));
+        allocations
+    }
+
+    fn register_token(contract: &mut TokenFactory, ft_contract: &str) {
+        register_token_with_supply(contract, ft_contract, 100_000_000, 8);
+    }
+
+    fn register_token_with_supply(
+        contract: &mut TokenFactory,
+        ft_contract: &str,
+        total_supply: Balance,
+        decimals: u8,
+    ) {
+        contract.register(
+            ft_contract.to_string(),
+            format!("deployer-{}", ft_contract),
+            WrappedBalance::from(total_supply),
+            "Test Token".to_string(),
+            "TEST".to_string(),
+            None,
+            None,
+            None,
+            token_allocations(),
+            decimals,
+        );
+    }
+
+    #[test]
+    fn test_portfolio_aggregates_promise_results() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        let account = json!({
+            "allocated_num": "92000000",
+            "claimed": "1000",
+            "claimable_amount": "500",
+            "vesting_interval": "86400000000000",
+        });
+        set_promise_results(vec![
+            PromiseResult::Successful(account.to_string().into_bytes()),
+            PromiseResult::Failed,
+        ]);
+
+        let portfolio = contract.on_portfolio_fetched(
+            "harrynguyen_near".to_string(),
+            vec![FT_CONTRACT.to_string()],
+            Some(1),
+        );
+
+        assert_eq!(portfolio.tokens.len(), 1);
+        let item = &portfolio.tokens[0];
+        assert_eq!(item.symbol, Some("TEST".to_string()));
+        assert_eq!(item.allocated.map(u128::from), Some(92_000_000));
+        assert_eq!(item.claimed.map(u128::from), Some(1000));
+        assert_eq!(item.claimable.map(u128::from), Some(500));
+        assert!(item.balance.is_none());
+        assert_eq!(portfolio.next_cursor, Some(1));
+    }
+
+    #[test]
+    fn test_portfolio_pages_by_token_id() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+        let allocatee = "harrynguyen_near".to_string();
+        contract.internal_add_user_token(
+            allocatee.clone(),
+            0,
+            "test000.tokenhub.testnet".to_string(),
+        );
+        contract.internal_add_user_token(
+            allocatee.clone(),
+            1,
+            "test001.tokenhub.testnet".to_string(),
+        );
+
+        contract.get_portfolio(allocatee, None, 1);
+        let receipts =
+            near_sdk::serde_json::to_string(&near_sdk::test_utils::get_created_receipts()).unwrap();
+        assert!(receipts.contains(r#"\"next_cursor\":1"#));
+    }
+
+    #[test]
+    fn test_portfolio_skips_unregistered_tokens() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        set_promise_results(vec![
+            PromiseResult::Failed,
+            PromiseResult::Failed,
+            PromiseResult::Failed,
+            PromiseResult::Failed,
+        ]);
+        let portfolio = contract.on_portfolio_fetched(
+            "harrynguyen_near".to_string(),
+            vec!["gone.tokenhub.testnet".to_string(), FT_CONTRACT.to_string()],
+            None,
+        );
+        assert_eq!(portfolio.tokens.len(), 1);
+        assert_eq!(portfolio.tokens[0].ft_contract, FT_CONTRACT.to_string());
+    }
+
+    #[test]
+    fn test_list_allocations_by_account_id() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        contract.init_token_allocation(FT_CONTRACT.to_string());
+
+        let allocations = contract
+            .list_allocations_by_account_id("harrynguyen_near".to_string(), None, 10)
+            .items;
+        assert_eq!(allocations.len(), 1);
+        assert_eq!(allocations[0].ft_contract, FT_CONTRACT);
+        assert_eq!(allocations[0].status, TokenStatus::Registered);
+        assert_eq!(
+            u128::from(allocations[0].allocated_amount.unwrap()),
+            92_000_000
+        );
+
+        assert!(contract
+            .list_allocations_by_account_id("unknown_near".to_string(), None, 10)
+            .items
+            .is_empty());
+    }
+
+    #[test]
+    fn test_allocation_amounts_of_large_supplies() {
+        let mut contract = setup_contract();
+        register_token_with_supply(&mut contract, FT_CONTRACT, 10u128.pow(35) + 5_000, 24);
+        contract.init_token_allocation(FT_CONTRACT.to_string());
+
+        let allocations = contract
+            .list_allocations_by_account_id("harrynguyen_near".to_string(), None, 10)
+            .items;
+        assert_eq!(
+            u128::from(allocations[0].allocated_amount.unwrap()),
+            92 * 10u128.pow(33) + 4_600
+        );
+    }
+
+    #[test]
+    fn test_creator_index() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        register_token(&mut contract, "test002.tokenhub.testnet");
+
+        let page = contract.list_tokens_by_creator("harrynguyen_near".to_string(), Some(1), 10);
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].ft_contract, "test002.tokenhub.testnet");
+        assert_eq!(
+            contract
+                .list_my_tokens("harrynguyen_near".to_string())
+                .len(),
+            2
+        );
+        assert!(contract
+            .list_my_tokens("unknown_near".to_string())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_cursor_pagination() {
+        let mut contract = setup_contract();
+        for i in 0..5 {
+            register_token(&mut contract, &format!("test00{}.tokenhub.testnet", i));
+        }
+        assert_eq!(contract.get_token_count(), 5);
+
+        let page = contract.list_token_ids(None, 2, None, None, None);
+        assert_eq!(
+            page.items,
+            vec!["test000.tokenhub.testnet", "test001.tokenhub.testnet"]
+        );
+        assert_eq!(page.next_cursor, Some(2));
+
+        // registering more tokens does not shift an ascending cursor
+        register_token(&mut contract, "test005.tokenhub.testnet");
+        let page = contract.list_token_ids(page.next_cursor, 2, None, None, None);
+        assert_eq!(
+            page.items,
+            vec!["test002.tokenhub.testnet", "test003.tokenhub.testnet"]
+        );
+
+        let page = contract.list_token_ids(None, 4, Some(true), None, None);
+        assert_eq!(page.items[0], "test005.tokenhub.testnet");
+        assert_eq!(page.next_cursor, Some(1));
+        let page = contract.list_token_ids(page.next_cursor, 4, Some(true), None, None);
+        assert_eq!(
+            page.items,
+            vec!["test001.tokenhub.testnet", "test000.tokenhub.testnet"]
+        );
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn test_callbacks_keep_creation_order() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.on_ft_contract_deployed("test000.tokenhub.testnet".to_string()));
+
+        let tokens = contract.list_token_contracts(0, 10, None);
+        assert_eq!(tokens[0].ft_contract, "test001.tokenhub.testnet");
+        assert_eq!(tokens[1].ft_contract, "test000.tokenhub.testnet");
+        assert_eq!(tokens[1].id, 0);
+        assert_eq!(tokens[1].status, TokenStatus::Deploying);
+        assert_eq!(
+            contract.tokens.keys_as_vector().get(0).unwrap(),
+            "test000.tokenhub.testnet"
+        );
+    }
+
+    #[test]
+    fn test_token_summaries_and_allocation_pages() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        let page = contract.list_tokens(None, 10, None, None, None);
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].allocation_count, 2);
+        assert_eq!(page.items[0].symbol, Some("TEST".to_string()));
+        assert_eq!(page.items[0].status, TokenStatus::Registered);
+
+        let first = contract.get_token_allocations(FT_CONTRACT.to_string(), 0, 1);
+        let rest = contract.get_token_allocations(FT_CONTRACT.to_string(), 1, 10);
+        assert_eq!(first.len(), 1);
+        assert_eq!(rest.len(), 1);
+        assert_eq!(
+            first[0].1.allocated_percent + rest[0].1.allocated_percent,
+            MAX_SUPPLY_PERCENT
+        );
+        assert_eq!(
+            contract
+                .get_token_allocations(FT_CONTRACT.to_string(), 1, u64::MAX)
+                .len(),
+            1
+        );
+
+        let tokens = contract.list_all_tokens();
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].allocation_count, 2);
+    }
+
+    #[test]
+    fn test_register_emits_event() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        let logs = get_logs();
+        assert_eq!(logs.len(), 1);
+        let event: Value =
+            near_sdk::serde_json::from_str(logs[0].strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!(event["standard"], "token_factory");
+        assert_eq!(event["version"], "1.0.0");
+        assert_eq!(event["event"], "token_registered");
+        assert_eq!(event["data"]["ft_contract"], FT_CONTRACT);
+        assert_eq!(event["data"]["id"], 0);
+    }
+
+    #[test]
+    fn test_allocation_init_event_counts_allocatees() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.on_allocation_init(FT_CONTRACT.to_string()));
+
+        let event: Value =
+            near_sdk::serde_json::from_str(get_logs()[0].strip_prefix("EVENT_JSON:").unwrap())
+                .unwrap();
+        assert_eq!(event["event"], "allocation_initialized");
+        assert_eq!(event["data"]["allocatee_count"], 2);
+    }
+
+    #[test]
+    fn test_change_feed() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+
+        let feed = contract.get_changes(Some(0), 10);
+        assert_eq!(feed.changes.len(), 1);
+        assert_eq!(feed.next_seq, 2);
+
+        for i in 0..events::CHANGE_FEED_CAPACITY {
+            if i % 50 == 0 {
+                // new context, the mocked blockchain caps the logs per call
+                set_promise_results(vec![]);
+            }
+            contract.record_event(FactoryEvent::MetadataCleared {
+                ft_contract: FT_CONTRACT.to_string(),
+            });
+        }
+        let feed = contract.get_changes(Some(0), 10);
+        assert_eq!(feed.oldest_seq, 2);
+        assert_eq!(feed.changes.len(), 10);
+        let value = near_sdk::serde_json::to_value(&feed).unwrap();
+        assert_eq!(value["changes"][0]["seq"], 2);
+        assert_eq!(value["changes"][0]["event"], "metadata_cleared");
+    }
+
+    #[test]
+    fn test_change_feed_bounds_reasons() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        set_predecessor("owner_near");
+        contract.set_moderation_status(
+            FT_CONTRACT.to_string(),
+            ModerationStatus::Flagged,
+            "é".repeat(events::MAX_FEED_REASON_LEN),
+        );
+
+        let feed = contract.get_changes(Some(0), 10);
+        match &feed.changes[0].event {
+            FactoryEvent::TokenModerated { reason, .. } => {
+                assert_eq!(reason.len(), events::MAX_FEED_REASON_LEN)
+            }
+            _ => panic!("expected the moderation change"),
+        }
+        assert_eq!(
+            contract
+                .get_moderation(FT_CONTRACT.to_string(), 0, 1)
+                .current
+                .unwrap()
+                .reason
+                .len(),
+            2 * events::MAX_FEED_REASON_LEN
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "E305: Total allocations is not 100%")]
+    fn test_register_reports_error_code() {
+        let mut contract = setup_contract();
+        let mut allocations: TokenAllocationInput = HashMap::new();
+        allocations.insert(TOKENHUB_TREASURY.to_string(), allocation(800));
+
+        contract.register(
+            FT_CONTRACT.to_string(),
+            format!("deployer-{}", FT_CONTRACT),
+            WrappedBalance::from(100_000_000),
+            "Test Token".to_string(),
+            "TEST".to_string(),
+            None,
+            None,
+            None,
+            allocations,
+            8,
+        );
+    }
+
+    #[test]
+    fn test_validate_registration_reports_all_issues() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        let mut allocations: TokenAllocationInput = HashMap::new();
+        let mut bad_vesting = allocation(10_000);
+        bad_vesting.vesting_interval = WrappedDuration::from(100 * 86_400_000_000_000);
+        allocations.insert("harrynguyen_near".to_string(), bad_vesting);
+
+        let report = contract.validate_registration(
+            FT_CONTRACT.to_string(),
+            FT_CONTRACT.to_string(),
+            WrappedBalance::from(0),
+            "".to_string(),
+            "TEST".to_string(),
+            None,
+            None,
+            None,
+            allocations,
+            8,
+            "harrynguyen_near".to_string(),
+        );
+        assert!(!report.valid);
+        let codes: Vec<u16> = report.issues.iter().map(|issue| issue.code).collect();
+        assert_eq!(codes, vec![301, 309, 314, 201, 306, 308]);
+        assert_eq!(
+            report.issues[5].account_id,
+            Some("harrynguyen_near".to_string())
+        );
+        assert!(u128::from(report.required_deposit) > contract.config.account_funding());
+    }
+
+    #[test]
+    fn test_overflowing_allocations_exceed_supply() {
+        let contract = setup_contract();
+        let mut allocations: TokenAllocationInput = HashMap::new();
+        allocations.insert(TOKENHUB_TREASURY.to_string(), allocation(u64::MAX / 2 + 1));
+        allocations.insert("harrynguyen_near".to_string(), allocation(u64::MAX / 2 + 1));
+
+        let report = contract.validate_registration(
+            FT_CONTRACT.to_string(),
+            format!("deployer-{}", FT_CONTRACT),
+            WrappedBalance::from(100_000_000),
+            "Test Token".to_string(),
+            "TEST".to_string(),
+            None,
+            None,
+            None,
+            allocations,
+            8,
+            "harrynguyen_near".to_string(),
+        );
+        assert!(report.issues.iter().any(|issue| issue.code == 304));
+    }
+
+    #[test]
+    fn test_registration_views_run_in_view_calls() {
+        let contract = setup_contract();
+        let mut context = get_context(vec![], true);
+        context.storage_usage = env::storage_usage();
+        testing_env!(context);
+
+        let report = contract.validate_registration(
+            FT_CONTRACT.to_string(),
+            format!("deployer-{}", FT_CONTRACT),
+            WrappedBalance::from(100_000_000),
+            "Test Token".to_string(),
+            "TEST".to_string(),
+            None,
+            None,
+            None,
+            token_allocations(),
+            8,
+            "harrynguyen_near".to_string(),
+        );
+        assert!(report.valid);
+        let quote = contract.quote_registration_cost(
+            FT_CONTRACT.to_string(),
+            format!("deployer-{}", FT_CONTRACT),
+            WrappedBalance::from(100_000_000),
+            "Test Token".to_string(),
+            "TEST".to_string(),
+            None,
+            None,
+            None,
+            token_allocations(),
+            8,
+            "harrynguyen_near".to_string(),
+        );
+        assert_eq!(quote.total, report.required_deposit);
+    }
+
+    #[test]
+    fn test_register_charges_quoted_cost() {
+        let mut contract = setup_contract();
+        // the second registration reuses the creator index and links a tree node
+        for ft_contract in &["test000.tokenhub.testnet", "test001.tokenhub.testnet"] {
+            let quote = contract.quote_registration_cost(
+                ft_contract.to_string(),
+                format!("deployer-{}", ft_contract),
+                WrappedBalance::from(100_000_000),
+                "Test Token".to_string(),
+                "TEST".to_string(),
+                None,
+                None,
+                None,
+                token_allocations(),
+                8,
+                "harrynguyen_near".to_string(),
+            );
+            register_token(&mut contract, ft_contract);
+
+            let cost = contract
+                .get_token_state(ft_contract.to_string())
+                .unwrap()
+                .cost
+                .unwrap();
+            assert_eq!(cost.storage_bytes, quote.storage_bytes);
+            assert_eq!(u128::from(cost.total), u128::from(quote.total));
+            assert_eq!(
+                u128::from(cost.total),
+                u128::from(cost.storage_cost) + contract.config.account_funding()
+            );
+
+            let refund = 10_000_000_000_000_000_000_000_000 - u128::from(cost.total);
+            let receipts = near_sdk::test_utils::get_created_receipts();
+            assert!(format!("{:?}", receipts.last().unwrap()).contains(&format!(
+                "Transfer(TransferAction {{ deposit: {} }})",
+                refund
+            )));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "E300: Attached deposit does not cover the registration cost")]
+    fn test_register_requires_cost_deposit() {
+        let mut contract = setup_contract();
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = contract.config.account_funding();
+        testing_env!(context);
+        register_token(&mut contract, FT_CONTRACT);
+    }
+
+    #[test]
+    fn test_pause_blocks_operations_but_not_callbacks() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        set_predecessor("owner_near");
+        contract.pause(Some(PausableOperation::FtContractDeployment));
+        contract.pause(Some(PausableOperation::FtContractDeployment));
+        let logs = get_logs();
+        assert_eq!(logs.len(), 1);
+        assert!(
+            logs[0].contains(r#""event":"paused","data":{"operation":"ft_contract_deployment"}"#)
+        );
+        assert!(contract.is_paused(PausableOperation::FtContractDeployment));
+        assert!(!contract.is_paused(PausableOperation::FtIssuance));
+
+        // a deployment started before the pause completes
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.on_ft_contract_deployed(FT_CONTRACT.to_string()));
+        contract.create_deployer_contract(FT_CONTRACT.to_string());
+
+        set_predecessor("owner_near");
+        contract.pause(None);
+        assert!(contract.is_paused(PausableOperation::Register));
+        assert!(contract.get_token_state(FT_CONTRACT.to_string()).is_some());
+
+        // lifting the global pause keeps the pause of the single operation
+        contract.unpause(None);
+        assert!(!contract.is_paused(PausableOperation::Register));
+        assert!(contract.is_paused(PausableOperation::FtContractDeployment));
+        contract.unpause(Some(PausableOperation::FtContractDeployment));
+        assert!(contract.get_pause_state().operations.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "E109")]
+    fn test_register_while_paused() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.pause(None);
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, FT_CONTRACT);
+    }
+
+    #[test]
+    #[should_panic(expected = "E110")]
+    fn test_pause_requires_pauser() {
+        let mut contract = setup_contract();
+        contract.pause(None);
+    }
+
+    #[test]
+    fn test_roles_limit_admin_methods() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        set_predecessor("owner_near");
+        contract.grant_role(Role::Moderator, "support_near".to_string());
+        contract.grant_role(Role::Moderator, "support_near".to_string());
+        assert_eq!(get_logs().len(), 1);
+        assert_eq!(
+            contract.get_role_members(Role::Moderator, 0, 10),
+            vec!["support_near".to_string()]
+        );
+
+        // clearing metadata deletes data, it is left to operators
+        contract.grant_role(Role::Operator, "operator_near".to_string());
+        let id = schedule_and_wait(
+            &mut contract,
+            "operator_near",
+            AdminOperation::ClearMetadata {
+                ft_contract: FT_CONTRACT.to_string(),
+            },
+        );
+        assert!(contract.execute_operation(id, None).done);
+
+        set_predecessor("owner_near");
+        contract.revoke_role(Role::Moderator, "support_near".to_string());
+        assert!(!contract.has_role(Role::Moderator, "support_near".to_string()));
+        assert!(contract.get_role_members(Role::Moderator, 0, 10).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "E110")]
+    fn test_moderator_can_not_clear_metadata() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        set_predecessor("owner_near");
+        contract.grant_role(Role::Moderator, "support_near".to_string());
+        set_predecessor("support_near");
+        contract.schedule_operation(AdminOperation::ClearMetadata {
+            ft_contract: FT_CONTRACT.to_string(),
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "E110")]
+    fn test_missing_role() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.grant_role(Role::Moderator, "support_near".to_string());
+        set_predecessor("support_near");
+        contract.schedule_operation(AdminOperation::Reset);
+    }
+
+    #[test]
+    fn test_curated_registrations_wait_for_review() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_curated(true);
+        contract.grant_role(Role::Moderator, "support_near".to_string());
+
+        // the quote covers the review request
+        set_predecessor("harrynguyen_near");
+        let quote = contract.quote_registration_cost(
+            FT_CONTRACT.to_string(),
+            format!("deployer-{}", FT_CONTRACT),
+            WrappedBalance::from(100_000_000),
+            "Test Token".to_string(),
+            "TEST".to_string(),
+            None,
+            None,
+            None,
+            token_allocations(),
+            8,
+            "harrynguyen_near".to_string(),
+        );
+        register_token(&mut contract, FT_CONTRACT);
+        register_token(&mut contract, "test002.tokenhub.testnet");
+        let cost = contract
+            .get_token_state(FT_CONTRACT.to_string())
+            .unwrap()
+            .cost
+            .unwrap();
+        assert_eq!(cost.storage_bytes, quote.storage_bytes);
+        let queue = contract.get_review_queue(0, 10);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue[0].creator, "harrynguyen_near".to_string());
+
+        set_predecessor("support_near");
+        contract.approve_token(FT_CONTRACT.to_string());
+        assert!(contract
+            .get_pending_review(FT_CONTRACT.to_string())
+            .is_none());
+        set_predecessor("harrynguyen_near");
+        contract.create_ft_contract(FT_CONTRACT.to_string());
+
+        let refund: u128 = contract
+            .get_token_state("test002.tokenhub.testnet".to_string())
+            .unwrap()
+            .cost
+            .unwrap()
+            .total
+            .into();
+        set_predecessor("support_near");
+        contract.reject_token(
+            "test002.tokenhub.testnet".to_string(),
+            "Impersonates another project".to_string(),
+        );
+        assert!(get_logs()
+            .last()
+            .unwrap()
+            .contains(r#""event":"token_rejected""#));
+        assert!(contract
+            .get_token_state("test002.tokenhub.testnet".to_string())
+            .is_none());
+        assert!(contract.get_review_queue(0, 10).is_empty());
+        let receipts = near_sdk::test_utils::get_created_receipts();
+        let receipt = format!("{:?}", receipts.last().unwrap());
+        assert!(receipt.contains("receiver_id: \"harrynguyen_near\""));
+        assert!(receipt.contains(&format!(
+            "Transfer(TransferAction {{ deposit: {} }})",
+            refund
+        )));
+    }
+
+    #[test]
+    #[should_panic(expected = "E403")]
+    fn test_deployment_waits_for_approval() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_curated(true);
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, FT_CONTRACT);
+        contract.create_ft_contract(FT_CONTRACT.to_string());
+    }
+
+    #[test]
+    fn test_moderation_status_and_listing_filters() {
+        let mut contract = setup_contract();
+        for ft_contract in &[
+            "test000.tokenhub.testnet",
+            "test001.tokenhub.testnet",
+            "test002.tokenhub.testnet",
+        ] {
+            register_token(&mut contract, ft_contract);
+        }
+        set_predecessor("owner_near");
+        contract.grant_role(Role::Moderator, "support_near".to_string());
+
+        set_predecessor("support_near");
+        contract.set_moderation_status(
+            "test000.tokenhub.testnet".to_string(),
+            ModerationStatus::Verified,
+            "Team is known".to_string(),
+        );
+        contract.set_moderation_status(
+            "test001.tokenhub.testnet".to_string(),
+            ModerationStatus::Flagged,
+            "Reported as a clone".to_string(),
+        );
+        contract.set_moderation_status(
+            "test001.tokenhub.testnet".to_string(),
+            ModerationStatus::Delisted,
+            "Clone of test000".to_string(),
+        );
+
+        let moderation = contract.get_moderation("test001.tokenhub.testnet".to_string(), 0, 10);
+        assert_eq!(moderation.status, ModerationStatus::Delisted);
+        assert_eq!(moderation.current.unwrap().reason, "Clone of test000");
+        assert_eq!(moderation.history_len, 2);
+        assert_eq!(moderation.history[0].status, ModerationStatus::Flagged);
+        assert_eq!(moderation.history[1].moderator, "support_near".to_string());
+        let moderation = contract.get_moderation("test002.tokenhub.testnet".to_string(), 0, 10);
+        assert_eq!(moderation.status, ModerationStatus::Unverified);
+        assert!(moderation.current.is_none());
+
+        let page = contract.list_token_ids(None, 1, None, None, Some(true));
+        assert_eq!(page.items, vec!["test000.tokenhub.testnet".to_string()]);
+        // the cursor skips the delisted token
+        let page = contract.list_token_ids(page.next_cursor, 1, None, None, Some(true));
+        assert_eq!(page.items, vec!["test002.tokenhub.testnet".to_string()]);
+        assert_eq!(page.next_cursor, None);
+
+        let page = contract.list_tokens(None, 10, None, Some(ModerationStatus::Verified), None);
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].moderation_status, ModerationStatus::Verified);
+        assert_eq!(contract.list_token_contracts(0, 10, Some(true)).len(), 2);
+        assert_eq!(contract.list_token_contracts(0, 10, None).len(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "E110")]
+    fn test_moderation_requires_moderator() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        contract.set_moderation_status(
+            FT_CONTRACT.to_string(),
+            ModerationStatus::Verified,
+            "Self verified".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_creator_allowlist() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_allowlist_enabled(true);
+        contract.allow_creator("harrynguyen_near".to_string());
+        assert_eq!(
+            contract.get_allowed_creators(0, 10),
+            vec!["harrynguyen_near".to_string()]
+        );
+
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, FT_CONTRACT);
+        assert!(
+            contract
+                .get_creator_usage("harrynguyen_near".to_string())
+                .allowed
+        );
+
+        set_predecessor("owner_near");
+        contract.disallow_creator("harrynguyen_near".to_string());
+        assert!(
+            !contract
+                .get_creator_usage("harrynguyen_near".to_string())
+                .allowed
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "E117")]
+    fn test_allowlist_blocks_other_creators() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_allowlist_enabled(true);
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, FT_CONTRACT);
+    }
+
+    #[test]
+    fn test_creator_limits() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_creator_limits(CreatorLimits {
+            max_unfinished: Some(2),
+            max_per_window: Some(3),
+            window: WrappedDuration::from(1_000),
+        });
+
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+        let usage = contract.get_creator_usage("harrynguyen_near".to_string());
+        assert_eq!(usage.unfinished, 2);
+        assert_eq!(usage.window_registrations, 2);
+        assert_eq!(u64::from(usage.window_ends_at.unwrap()), 1_000);
+
+        // a finished registration frees a slot
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.on_allocation_init("test000.tokenhub.testnet".to_string()));
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, "test002.tokenhub.testnet");
+        let usage = contract.get_creator_usage("harrynguyen_near".to_string());
+        assert_eq!(usage.unfinished, 2);
+        assert_eq!(usage.window_registrations, 3);
+
+        // the window ends 1_000 ns after its first registration
+        let mut context = get_context(vec![], false);
+        context.storage_usage = env::storage_usage();
+        context.block_timestamp = 1_000;
+        testing_env!(context);
+        let usage = contract.get_creator_usage("harrynguyen_near".to_string());
+        assert_eq!(usage.window_registrations, 0);
+        assert!(usage.window_ends_at.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "E324")]
+    fn test_registrations_per_window_limit() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_creator_limits(CreatorLimits {
+            max_per_window: Some(1),
+            ..CreatorLimits::default()
+        });
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_allocation_init("test000.tokenhub.testnet".to_string());
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+    }
+
+    #[test]
+    #[should_panic(expected = "E323")]
+    fn test_unfinished_registrations_limit() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_creator_limits(CreatorLimits {
+            max_unfinished: Some(1),
+            ..CreatorLimits::default()
+        });
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+    }
+
+    #[test]
+    fn test_two_step_owner_transfer() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.propose_owner("dao_near".to_string());
+        assert_eq!(contract.get_pending_owner(), Some("dao_near".to_string()));
+        contract.cancel_owner_proposal();
+        assert!(contract.get_pending_owner().is_none());
+
+        contract.propose_owner("dao_near".to_string());
+        set_predecessor("dao_near");
+        contract.accept_owner();
+        assert_eq!(contract.get_owner(), "dao_near");
+        assert!(contract.get_pending_owner().is_none());
+        assert!(get_logs()[0].contains(r#""event":"owner_changed""#));
+
+        // the new owner manages the roles
+        contract.grant_role(Role::Pauser, "owner_near".to_string());
+        assert!(contract.has_role(Role::Pauser, "owner_near".to_string()));
+    }
+
+    #[test]
+    #[should_panic(expected = "E113")]
+    fn test_accept_owner_requires_proposed_owner() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.propose_owner("dao_near".to_string());
+        set_predecessor("harrynguyen_near");
+        contract.accept_owner();
+    }
+
+    #[test]
+    fn test_timelock_schedule_cancel_execute() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        set_predecessor("owner_near");
+        contract.grant_role(Role::Operator, "operator_near".to_string());
+        contract.grant_role(Role::Guardian, "guardian_near".to_string());
+
+        set_predecessor("operator_near");
+        let id = contract.schedule_operation(AdminOperation::Reset);
+        let pending = contract.list_scheduled_operations(0, 10);
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].operation, AdminOperation::Reset);
+        assert_eq!(u64::from(pending[0].executable_at), DEFAULT_TIMELOCK_DELAY);
+
+        set_predecessor("guardian_near");
+        contract.cancel_operation(id);
+        assert!(contract.list_scheduled_operations(0, 10).is_empty());
+
+        let id = schedule_and_wait(&mut contract, "operator_near", AdminOperation::Reset);
+        assert!(contract.execute_operation(id, None).done);
+        assert_eq!(contract.get_token_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "E115")]
+    fn test_timelock_blocks_early_execution() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        let id = contract.schedule_operation(AdminOperation::Reset);
+        contract.execute_operation(id, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "E116")]
+    fn test_started_operation_can_not_be_cancelled() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        let id = schedule_and_wait(&mut contract, "owner_near", AdminOperation::Reset);
+        assert!(!contract.execute_operation(id, Some(1)).done);
+        contract.cancel_operation(id);
+    }
+
+    #[test]
+    fn test_reset_counts_tokens_across_calls() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+        let id = schedule_and_wait(&mut contract, "owner_near", AdminOperation::Reset);
+
+        // two allocations and the token per call
+        assert!(!contract.execute_operation(id, Some(3)).done);
+        assert!(contract.execute_operation(id, Some(3)).done);
+        let feed = contract.get_changes(None, 10);
+        let reset = feed.changes.iter().find_map(|change| match change.event {
+            FactoryEvent::RegistryReset { token_count } => Some(token_count),
+            _ => None,
+        });
+        assert_eq!(reset, Some(2));
+    }
+
+    #[test]
+    fn test_started_operation_on_removed_token_completes() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        let unregister = schedule_and_wait(
+            &mut contract,
+            "owner_near",
+            AdminOperation::Unregister {
+                ft_contract: FT_CONTRACT.to_string(),
+            },
+        );
+        assert!(!contract.execute_operation(unregister, Some(1)).done);
+
+        let reset = schedule_and_wait(&mut contract, "owner_near", AdminOperation::Reset);
+        assert!(contract.execute_operation(reset, None).done);
+        assert!(contract.execute_operation(unregister, None).done);
+        assert!(contract.list_scheduled_operations(0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_timelock_delay_change() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_timelock_delay(WrappedDuration::from(MIN_TIMELOCK_DELAY));
+        let id = contract.schedule_operation(AdminOperation::Reset);
+        assert_eq!(
+            contract.get_scheduled_operation(id).unwrap().executable_at,
+            WrappedTimestamp::from(MIN_TIMELOCK_DELAY)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "E118")]
+    fn test_timelock_delay_has_a_minimum() {
+        let mut contract = setup_contract();
+        set_predecessor("owner_near");
+        contract.set_timelock_delay(WrappedDuration::from(0));
+    }
+
+    #[test]
+    fn test_vesting_rules() {
+        testing_env!(get_context(vec![], false));
+        let valid = TokenAllocation::from(allocation(100));
+        assert!(validation::validate_allocation(&valid, Some(0)).is_empty());
+
+        let mut invalid = valid.clone();
+        invalid.allocated_percent = 0;
+        invalid.initial_release = 10;
+        invalid.vesting_interval = 0;
+        invalid.vesting_end_time = invalid.vesting_start_time - 1;
+        assert_eq!(
+            validation::validate_allocation(&invalid, Some(invalid.vesting_start_time + 1)),
+            vec![
+                FactoryError::ZeroAllocation,
+                FactoryError::InitialReleaseExceedsAllocation,
+                FactoryError::ZeroVestingInterval,
+                FactoryError::VestingStartInPast,
+                FactoryError::VestingEndBeforeStart,
+            ]
+        );
+
+        let mut too_long = valid;
+        too_long.vesting_end_time =
+            too_long.vesting_start_time + validation::MAX_VESTING_DURATION + 1;
+        assert_eq!(
+            validation::validate_allocation(&too_long, None),
+            vec![FactoryError::VestingDurationTooLong]
+        );
+        assert_eq!(
+            FactoryError::VestingDurationTooLong.to_string(),
+            "E321: Vesting duration must not exceed 3650 days"
+        );
+        assert_eq!(
+            FactoryError::TooManyAllocatees.message(),
+            format!(
+                "Number of allocatees must not exceed {}",
+                validation::MAX_ALLOCATEES
+            )
+        );
+    }
+
+    #[test]
+    fn test_unknown_token_lookups() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+
+        assert!(contract.get_token_state(FT_CONTRACT.to_string()).is_some());
+        assert!(contract
+            .get_token_state("unknown.testnet".to_string())
+            .is_none());
+        let states = contract
+            .list_token_states(vec![FT_CONTRACT.to_string(), "unknown.testnet".to_string()]);
+        assert!(states[0].is_some() && states[1].is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "E200: Token is not registered")]
+    fn test_write_on_unknown_token() {
+        let mut contract = setup_contract();
+        contract.create_ft_contract("unknown.testnet".to_string());
+    }
+
+    // writes the registry as deployed before versioning: untagged `OldState`
+    // records and collections under the original literal prefixes
+    fn write_baseline_registry(token_count: u64) {
+        testing_env!(get_context(vec![], false));
+        let mut admins = UnorderedSet::new(b"admins".to_vec());
+        admins.insert(&"admin_near".to_string());
+        let mut tokens: UnorderedMap<TokenId, VersionedState> =
+            UnorderedMap::new(b"tokenspec".to_vec());
+        for i in 0..token_count {
+            let ft_contract = format!("test00{}.tokenhub.testnet", i);
+            let mut prefix = vec![b'a'];
+            prefix.extend(env::sha256(format!("{}@0", ft_contract).as_bytes()));
+            let mut allocations = UnorderedMap::new(prefix);
+            for (account_id, allocation) in token_allocations() {
+                allocations.insert(&account_id, &TokenAllocation::from(allocation));
+            }
+            let state = legacy::OldState {
+                ft_contract: ft_contract.clone(),
+                ft_metadata: Some(FTMetadata {
+                    total_supply: 100_000_000,
+                    token_name: "Test Token".to_string(),
+                    symbol: "TEST".to_string(),
+                    icon: None,
+                    reference: None,
+                    reference_hash: None,
+                    decimals: 8,
+                }),
+                ft_deployer: format!("deployer-{}", ft_contract),
+                creator: "harrynguyen_near".to_string(),
+                allocations,
+                ft_contract_deployed: 0,
+                deployer_contract_deployed: 0,
+                ft_issued: 0,
+                allocation_initialized: 0,
+            };
+            // the first token went through every step
+            let state = if i == 0 {
+                legacy::OldState {
+                    ft_contract_deployed: 1,
+                    deployer_contract_deployed: 1,
+                    ft_issued: 1,
+                    allocation_initialized: 1,
+                    ..state
+                }
+            } else {
+                state
+            };
+            tokens.insert(&ft_contract, &VersionedState::V0(state));
+        }
+        let mut user_token_map = legacy::baseline_user_token_map();
+        let mut user_tokens = UnorderedSet::new(b"harrynguyen_near#0".to_vec());
+        user_tokens.insert(&"test000.tokenhub.testnet".to_string());
+        user_token_map.insert(&"harrynguyen_near".to_string(), &user_tokens);
+        env::state_write(&legacy::TokenFactoryV0 {
+            owner_id: "owner_near".to_string(),
+            admins,
+            tokens,
+            user_token_map,
+        });
+    }
+
+    #[test]
+    fn test_baseline_token_state_is_read_after_migrate() {
+        write_baseline_registry(2);
+        set_predecessor("owner_near");
+        let mut contract = TokenFactory::migrate();
+        let stored =
+            |contract: &TokenFactory| contract.tokens.values_as_vector().get_raw(1).unwrap();
+        assert_ne!(stored(&contract)[0], VERSIONED_STATE_MARKER);
+
+        let state = contract.get_token_state(FT_CONTRACT.to_string()).unwrap();
+        assert_eq!((state.id, state.creator.as_str()), (1, "harrynguyen_near"));
+        assert_eq!(state.allocations.len(), 2);
+
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        assert!(contract.on_ft_contract_deployed(FT_CONTRACT.to_string()));
+        assert_eq!(stored(&contract)[..2], [VERSIONED_STATE_MARKER, 1]);
+        let state = contract.get_token_state(FT_CONTRACT.to_string()).unwrap();
+        assert_eq!((state.id, state.ft_contract_deployed), (1, 1));
+
+        // the ids of the baseline tokens are reserved
+        register_token(&mut contract, "test009.tokenhub.testnet");
+        assert_eq!(
+            contract
+                .get_token_state("test009.tokenhub.testnet".to_string())
+                .unwrap()
+                .id,
+            2
+        );
+    }
+
+    #[test]
+    fn test_baseline_token_keeps_its_id_when_moved() {
+        write_baseline_registry(3);
+        set_predecessor("owner_near");
+        let mut contract = TokenFactory::migrate();
+        let id = schedule_and_wait(
+            &mut contract,
+            "owner_near",
+            AdminOperation::Unregister {
+                ft_contract: "test000.tokenhub.testnet".to_string(),
+            },
+        );
+        assert!(contract.execute_operation(id, None).done);
+
+        // test002 took the slot of test000
+        assert_eq!(
+            contract.tokens.keys_as_vector().get(0),
+            Some("test002.tokenhub.testnet".to_string())
+        );
+        let state = contract
+            .get_token_state("test002.tokenhub.testnet".to_string())
+            .unwrap();
+        assert_eq!(state.id, 2);
+    }
+
+    #[test]
+    fn test_migrate_baseline_and_backfill_in_pieces() {
+        write_baseline_registry(3);
+        set_predecessor("owner_near");
+        let mut contract = TokenFactory::migrate();
+        assert_eq!(contract.get_state_version(), admins::STATE_VERSION);
+        assert_eq!(contract.get_data_version(), 0);
+        // admins could do everything, they get every role
+        assert_eq!(
+            contract.get_account_roles("admin_near".to_string()).len(),
+            roles::ROLES.len()
+        );
+
+        let id = schedule_and_wait(
+            &mut contract,
+            "admin_near",
+            AdminOperation::StartMigration {
+                target_version: None,
+            },
+        );
+        contract.execute_operation(id, None);
+        let progress = contract.run_migration(2);
+        assert_eq!((progress.processed, progress.total), (2, 3));
+        assert!(progress.completed_at.is_none());
+        assert_eq!(contract.get_data_version(), 0);
+
+        // a registration during the migration takes an id after the baseline ones
+        set_predecessor("harrynguyen_near");
+        register_token(&mut contract, "test009.tokenhub.testnet");
+        set_predecessor("admin_near");
+        let progress = contract.run_migration(2);
+        assert_eq!(progress.processed, 4);
+        assert!(progress.completed_at.is_some());
+        assert_eq!(contract.get_data_version(), migration::DATA_VERSION);
+
+        let page = contract.list_token_ids(None, 10, None, None, None);
+        assert_eq!(
+            page.items,
+            vec![
+                "test000.tokenhub.testnet",
+                "test001.tokenhub.testnet",
+                "test002.tokenhub.testnet",
+                "test009.tokenhub.testnet",
+            ]
+        );
+        let page = contract.list_tokens(None, 10, None, None, None);
+        assert_eq!(page.items[3].id, 3);
+        assert_eq!(
+            contract
+                .list_tokens_by_creator("harrynguyen_near".to_string(), None, 10)
+                .items
+                .len(),
+            4
+        );
+        // only the initialized baseline token is indexed for its allocatees
+        assert_eq!(
+            contract.list_tokens_by_account_id("harrynguyen_near".to_string()),
+            vec!["test000.tokenhub.testnet"]
+        );
+        assert!(legacy::baseline_user_token_map()
+            .get(&"harrynguyen_near".to_string())
+            .is_none());
+        let token = contract.internal_get_token(&"test000.tokenhub.testnet".to_string());
+        assert!(storage::uses_storage_key(
+            &token.allocations,
+            &StorageKey::allocations(token.id, &token.ft_contract)
+        ));
+        assert_eq!(token.allocations.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "E104: State is already at the current version")]
+    fn test_migrate_refuses_current_state() {
+        setup_contract();
+        set_predecessor("owner_near");
+        TokenFactory::migrate();
+    }
+
+    #[test]
+    fn test_migration_rekeys_nested_collections() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        let ft_contract = FT_CONTRACT.to_string();
+
+        // allocations stored under an ad hoc prefix, as before the key scheme
+        let mut token = contract.internal_get_token(&ft_contract);
+        let mut legacy = UnorderedMap::new(b"a-legacy".to_vec());
+        legacy.extend(token.allocations.iter());
+        token.allocations.clear();
+        token.allocations = legacy;
+        contract.internal_set_token(token);
+        contract.data_version = 3;
+
+        let id = schedule_and_wait(
+            &mut contract,
+            "owner_near",
+            AdminOperation::StartMigration {
+                target_version: None,
+            },
+        );
+        contract.execute_operation(id, None);
+        contract.run_migration(10);
+
+        let token = contract.internal_get_token(&ft_contract);
+        let key = StorageKey::allocations(token.id, &ft_contract);
+        assert!(storage::uses_storage_key(&token.allocations, &key));
+        assert_eq!(token.allocations.len(), 2);
+        assert_eq!(
+            token
+                .allocations
+                .get(&TOKENHUB_TREASURY.to_string())
+                .unwrap()
+                .allocated_percent,
+            800
+        );
+        assert!(!env::storage_has_key(b"a-legacyk\0\0\0\0\0\0\0\0"));
+    }
+
+    #[test]
+    fn test_unregister_and_reset_delete_nested_storage() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, "test000.tokenhub.testnet");
+        register_token(&mut contract, "test001.tokenhub.testnet");
+        let allocatee = "harrynguyen_near".to_string();
+        contract.internal_add_user_token(
+            allocatee.clone(),
+            0,
+            "test000.tokenhub.testnet".to_string(),
+        );
+        contract.internal_add_user_token(
+            allocatee.clone(),
+            1,
+            "test001.tokenhub.testnet".to_string(),
+        );
+
+        let id = schedule_and_wait(
+            &mut contract,
+            "owner_near",
+            AdminOperation::Unregister {
+                ft_contract: "test000.tokenhub.testnet".to_string(),
+            },
+        );
+        let report = contract.execute_operation(id, Some(1));
+        assert!(!report.done && report.bytes_freed > 0);
+        assert!(contract
+            .get_token_state("test000.tokenhub.testnet".to_string())
+            .is_some());
+        let report = contract.execute_operation(id, None);
+        assert!(report.done);
+        assert!(contract
+            .get_token_state("test000.tokenhub.testnet".to_string())
+            .is_none());
+        assert_eq!(
+            contract.list_tokens_by_account_id(allocatee.clone()),
+            vec!["test001.tokenhub.testnet".to_string()]
+        );
+
+        let id = schedule_and_wait(&mut contract, "owner_near", AdminOperation::Reset);
+        assert!(!contract.execute_operation(id, Some(2)).done);
+        assert!(contract.execute_operation(id, Some(2)).done);
+        assert!(contract.get_scheduled_operation(id).is_none());
+        assert_eq!(contract.get_token_count(), 0);
+        assert!(contract
+            .list_tokens_by_account_id(allocatee.clone())
+            .is_empty());
+        assert!(contract
+            .list_tokens_by_creator(allocatee, None, 10)
+            .items
+            .is_empty());
+        // nested allocation entries are deleted too
+        let mut allocation_key =
+            StorageKey::allocations(1, &"test001.tokenhub.testnet".to_string())
+                .try_to_vec()
+                .unwrap();
+        allocation_key.push(b'i');
+        allocation_key.extend(TOKENHUB_TREASURY.to_string().try_to_vec().unwrap());
+        assert!(!env::storage_has_key(&allocation_key));
+    }
+
+    #[test]
+    fn test_compaction_keeps_summary_and_refunds_storage() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        let ft_contract = FT_CONTRACT.to_string();
+        contract.internal_add_user_token("harrynguyen_near".to_string(), 0, ft_contract.clone());
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_allocation_init(ft_contract.clone());
+
+        let report = contract.compact_token(ft_contract.clone(), Some(1));
+        assert!(!report.done);
+        let report = contract.compact_token(ft_contract.clone(), None);
+        assert!(report.done && report.bytes_freed > 0);
+
+        let state = contract.get_token_state(ft_contract.clone()).unwrap();
+        assert!(state.ft_metadata.is_none() && state.allocations.is_empty());
+        let summary = state.compacted.unwrap();
+        assert_eq!(summary.allocation_count, 2);
+        assert_eq!(u128::from(summary.treasury_amount), 8_000_000);
+        assert!(u128::from(summary.storage_refunded) > 0);
+        assert!(summary.completed_at.is_some());
+
+        let tokens = contract.list_tokens(None, 10, None, None, None).items;
+        assert_eq!(tokens[0].symbol, Some("TEST".to_string()));
+        assert_eq!(tokens[0].allocation_count, 2);
+        assert!(tokens[0].compacted);
+        let allocations = contract
+            .list_allocations_by_account_id("harrynguyen_near".to_string(), None, 10)
+            .items;
+        assert_eq!(allocations.len(), 1);
+        assert!(allocations[0].allocation.is_none());
+    }
+
+    #[test]
+    fn test_compaction_of_large_supplies() {
+        let mut contract = setup_contract();
+        register_token_with_supply(&mut contract, FT_CONTRACT, 10u128.pow(35), 24);
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_allocation_init(FT_CONTRACT.to_string());
+
+        assert!(contract.compact_token(FT_CONTRACT.to_string(), None).done);
+        let summary = contract
+            .get_token_state(FT_CONTRACT.to_string())
+            .unwrap()
+            .compacted
+            .unwrap();
+        assert_eq!(u128::from(summary.treasury_amount), 8 * 10u128.pow(33));
+    }
+
+    #[test]
+    fn test_unregister_compacted_token_cleans_allocatee_index() {
+        let mut contract = setup_contract();
+        register_token(&mut contract, FT_CONTRACT);
+        let ft_contract = FT_CONTRACT.to_string();
+        let allocatee = "harrynguyen_near".to_string();
+        contract.internal_add_user_token(allocatee.clone(), 0, ft_contract.clone());
+        contract.internal_add_user_token(TOKENHUB_TREASURY.to_string(), 0, ft_contract.clone());
+        set_promise_results(vec![PromiseResult::Successful(vec![])]);
+        contract.on_allocation_init(ft_contract.clone());
+        assert!(contract.compact_token(ft_contract.clone(), None).done);
+
+        let id = schedule_and_wait(
+            &mut contract,
+            "owner_near",
+            AdminOperation::Unregister {
+                ft_contract: ft_contract.clone(),
+            },
+        );
+        assert!(!contract.execute_operation(id, Some(1)).done);
+        assert!(contract.get_token_state(ft_contract.clone()).is_some());
+        assert!(contract.execute_operation(id, None).done);
+        assert!(contract.get_token_state(ft_contract).is_none());
+        assert!(contract.list_tokens_by_account_id(allocatee).is_empty());
+        assert!(contract
+            .list_tokens_by_account_id(TOKENHUB_TREASURY.to_string())
+            .is_empty());
+    }
 }
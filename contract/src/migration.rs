@@ -0,0 +1,156 @@
+use crate::errors::{require, FactoryError};
+use crate::storage::{uses_storage_key, StorageKey};
+use crate::*;
+
+/// Latest version of the token data. Every version is a backfill over all
+/// tokens, applied by `run_migration` once a `StartMigration` operation is
+/// executed; bump it when adding a step below.
+pub const DATA_VERSION: u32 = 4;
+
+/// A pass over all tokens upgrading their data to `target_version`.
+/// `cursor` is the index in `tokens` of the next token to process.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct MigrationJob {
+    pub target_version: u32,
+    pub cursor: u64,
+    pub started_at: Timestamp,
+    pub completed_at: Option<Timestamp>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MigrationProgress {
+    pub data_version: u32,
+    pub target_version: u32,
+    pub processed: u64,
+    pub total: u64,
+    pub started_at: WrappedTimestamp,
+    pub completed_at: Option<WrappedTimestamp>,
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Upgrades the next `limit` tokens of the running migration.
+    /// Completes the migration once every token is processed.
+    pub fn run_migration(&mut self, limit: u64) -> MigrationProgress {
+        self.assert_role(Role::Migrator);
+        let mut job = self
+            .migration
+            .take()
+            .filter(|job| job.completed_at.is_none())
+            .unwrap_or_else(|| FactoryError::NoMigrationRunning.panic());
+
+        let end = std::cmp::min(job.cursor + limit, self.tokens.len());
+        for index in job.cursor..end {
+            for version in self.data_version + 1..=job.target_version {
+                self.internal_migrate_token(index, version);
+            }
+        }
+        job.cursor = std::cmp::max(job.cursor, end);
+
+        if job.cursor >= self.tokens.len() {
+            job.completed_at = Some(env::block_timestamp());
+            self.data_version = job.target_version;
+            self.record_event(FactoryEvent::MigrationCompleted {
+                data_version: self.data_version,
+            });
+        }
+        self.migration = Some(job);
+        self.get_migration().unwrap()
+    }
+
+    /// Progress of the running or last completed migration
+    pub fn get_migration(&self) -> Option<MigrationProgress> {
+        self.migration.as_ref().map(|job| MigrationProgress {
+            data_version: self.data_version,
+            target_version: job.target_version,
+            processed: job.cursor,
+            total: self.tokens.len(),
+            started_at: WrappedTimestamp::from(job.started_at),
+            completed_at: job.completed_at.map(WrappedTimestamp::from),
+        })
+    }
+
+    pub fn get_data_version(&self) -> u32 {
+        self.data_version
+    }
+}
+
+impl TokenFactory {
+    /// Starts upgrading the token data to `target_version` (the latest by default)
+    pub(crate) fn internal_start_migration(&mut self, target_version: Option<u32>) {
+        require(
+            !self.internal_migration_running(),
+            FactoryError::MigrationInProgress,
+        );
+        let target_version = target_version.unwrap_or(DATA_VERSION);
+        require(
+            target_version > self.data_version && target_version <= DATA_VERSION,
+            FactoryError::InvalidMigrationTarget,
+        );
+
+        self.migration = Some(MigrationJob {
+            target_version,
+            cursor: 0,
+            started_at: env::block_timestamp(),
+            completed_at: None,
+        });
+        self.record_event(FactoryEvent::MigrationStarted {
+            from_version: self.data_version,
+            target_version,
+        });
+    }
+
+    pub(crate) fn internal_migration_running(&self) -> bool {
+        self.migration
+            .as_ref()
+            .map(|job| job.completed_at.is_none())
+            .unwrap_or(false)
+    }
+
+    /// Upgrades the token at `index` to `version`. Every step is idempotent.
+    fn internal_migrate_token(&mut self, index: u64, version: u32) {
+        let contract_id = self.tokens.keys_as_vector().get(index).unwrap();
+        match version {
+            // numeric ids, storing the id baseline records are read with
+            1 => {
+                let state = self.internal_get_token(&contract_id);
+                self.token_order.insert(&state.id, &contract_id);
+                self.internal_set_token(state);
+            }
+            // creator index
+            2 => {
+                let state = self.internal_get_token(&contract_id);
+                self.internal_add_creator_token(state.creator, state.id, contract_id);
+            }
+            // allocatee index by id, deleting the sets of the baseline index.
+            // Like `init_token_allocation`, only initialized tokens are indexed.
+            3 => {
+                let state = self.internal_get_token(&contract_id);
+                let mut baseline_index = legacy::baseline_user_token_map();
+                for allocatee in state.allocations.keys() {
+                    if let Some(mut tokens) = baseline_index.remove(&allocatee) {
+                        tokens.clear();
+                    }
+                    if state.allocation_initialized == 1 {
+                        self.internal_add_user_token(allocatee, state.id, contract_id.clone());
+                    }
+                }
+            }
+            // hashed storage keys for the allocations
+            4 => {
+                let mut state = self.internal_get_token(&contract_id);
+                let key = StorageKey::allocations(state.id, &contract_id);
+                if !uses_storage_key(&state.allocations, &key) {
+                    let mut allocations = UnorderedMap::new(key);
+                    allocations.extend(state.allocations.iter());
+                    state.allocations.clear();
+                    state.allocations = allocations;
+                }
+
+                self.internal_set_token(state);
+            }
+            _ => FactoryError::InvalidMigrationTarget.panic(),
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use near_sdk::collections::Vector;
+
+use crate::errors::{require, FactoryError};
+use crate::roles::Role;
+use crate::storage::StorageKey;
+use crate::*;
+
+/// Badge shown by explorers. Tokens no moderator has looked at are unverified.
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationStatus {
+    Unverified,
+    Verified,
+    Flagged,
+    // hidden by listing views that are asked to
+    Delisted,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ModerationRecord {
+    pub status: ModerationStatus,
+    pub reason: String,
+    pub moderator: AccountId,
+    pub updated_at: Timestamp,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WrappedModerationRecord {
+    pub status: ModerationStatus,
+    pub reason: String,
+    pub moderator: AccountId,
+    pub updated_at: WrappedTimestamp,
+}
+
+impl From<ModerationRecord> for WrappedModerationRecord {
+    fn from(record: ModerationRecord) -> Self {
+        WrappedModerationRecord {
+            status: record.status,
+            reason: record.reason,
+            moderator: record.moderator,
+            updated_at: WrappedTimestamp::from(record.updated_at),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenModeration {
+    pub status: ModerationStatus,
+    // None while the token was never moderated
+    pub current: Option<WrappedModerationRecord>,
+    // oldest first, paginated
+    pub history: Vec<WrappedModerationRecord>,
+    pub history_len: u64,
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    pub fn set_moderation_status(
+        &mut self,
+        ft_contract: AccountId,
+        status: ModerationStatus,
+        reason: String,
+    ) {
+        self.assert_role(Role::Moderator);
+        let token = self.internal_get_token(&ft_contract);
+        require(!reason.is_empty(), FactoryError::EmptyModerationReason);
+
+        let mut history = self
+            .moderation
+            .get(&ft_contract)
+            .unwrap_or_else(|| Vector::new(StorageKey::moderation_history(token.id, &ft_contract)));
+        history.push(&ModerationRecord {
+            status,
+            reason: reason.clone(),
+            moderator: env::predecessor_account_id(),
+            updated_at: env::block_timestamp(),
+        });
+        self.moderation.insert(&ft_contract, &history);
+
+        self.record_event(FactoryEvent::TokenModerated {
+            ft_contract,
+            status,
+            reason,
+        });
+    }
+
+    /// Current status of the token and up to `limit` records of its history
+    /// from `from_index`
+    pub fn get_moderation(
+        &self,
+        ft_contract: AccountId,
+        from_index: u64,
+        limit: u64,
+    ) -> TokenModeration {
+        let history = self.moderation.get(&ft_contract);
+        let history_len = history.as_ref().map(|history| history.len()).unwrap_or(0);
+        let current = history
+            .as_ref()
+            .and_then(|history| history.get(history_len.checked_sub(1)?));
+
+        TokenModeration {
+            status: current
+                .as_ref()
+                .map(|record| record.status)
+                .unwrap_or(ModerationStatus::Unverified),
+            current: current.map(WrappedModerationRecord::from),
+            history: history
+                .map(|history| {
+                    (from_index..std::cmp::min(from_index.saturating_add(limit), history_len))
+                        .filter_map(|index| history.get(index))
+                        .map(WrappedModerationRecord::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            history_len,
+        }
+    }
+}
+
+impl TokenFactory {
+    pub(crate) fn internal_moderation_status(&self, ft_contract: &TokenId) -> ModerationStatus {
+        self.moderation
+            .get(ft_contract)
+            .and_then(|history| history.get(history.len().checked_sub(1)?))
+            .map(|record| record.status)
+            .unwrap_or(ModerationStatus::Unverified)
+    }
+
+    /// Whether listings asked for `moderation_status`, or to hide delisted
+    /// tokens, show the token
+    pub(crate) fn internal_is_listed(
+        &self,
+        ft_contract: &TokenId,
+        moderation_status: Option<ModerationStatus>,
+        hide_delisted: bool,
+    ) -> bool {
+        let status = self.internal_moderation_status(ft_contract);
+        moderation_status
+            .map(|wanted| wanted == status)
+            .unwrap_or(true)
+            && !(hide_delisted && status == ModerationStatus::Delisted)
+    }
+
+    /// Deletes the moderation history of a token that is being removed
+    pub(crate) fn internal_remove_moderation(&mut self, ft_contract: &TokenId) {
+        if let Some(mut history) = self.moderation.remove(ft_contract) {
+            history.clear();
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use crate::errors::{require, FactoryError};
+use crate::*;
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Proposes `new_owner`, who becomes the owner once it calls `accept_owner`.
+    /// Replaces any pending proposal.
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        self.assert_owner_id();
+        require(
+            env::is_valid_account_id(new_owner.as_bytes()),
+            FactoryError::InvalidOwner,
+        );
+        self.pending_owner = Some(new_owner.clone());
+        self.record_event(FactoryEvent::OwnerProposed {
+            owner_id: self.owner_id.clone(),
+            new_owner,
+        });
+    }
+
+    pub fn cancel_owner_proposal(&mut self) {
+        self.assert_owner_id();
+        let new_owner = self
+            .pending_owner
+            .take()
+            .unwrap_or_else(|| FactoryError::NoPendingOwner.panic());
+        self.record_event(FactoryEvent::OwnerProposalCancelled { new_owner });
+    }
+
+    /// Called by the proposed owner to take over
+    pub fn accept_owner(&mut self) {
+        require(
+            self.pending_owner.as_ref() == Some(&env::predecessor_account_id()),
+            FactoryError::NotPendingOwner,
+        );
+        let new_owner = self.pending_owner.take().unwrap();
+        let previous_owner = std::mem::replace(&mut self.owner_id, new_owner.clone());
+        self.record_event(FactoryEvent::OwnerChanged {
+            previous_owner,
+            owner_id: new_owner,
+        });
+    }
+
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner.clone()
+    }
+}
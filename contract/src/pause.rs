@@ -0,0 +1,85 @@
+use crate::errors::{require, FactoryError};
+use crate::*;
+
+/// Operations that can be paused on their own. Callbacks are never paused,
+/// so steps already in flight complete.
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum PausableOperation {
+    Register,
+    FtContractDeployment,
+    DeployerContractDeployment,
+    FtIssuance,
+    AllocationInit,
+    Compaction,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default, Deserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PauseState {
+    // pauses every operation
+    pub all: bool,
+    pub operations: Vec<PausableOperation>,
+}
+
+impl PauseState {
+    pub fn is_paused(&self, operation: PausableOperation) -> bool {
+        self.all || self.operations.contains(&operation)
+    }
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Pauses `operation`, or every operation when None
+    pub fn pause(&mut self, operation: Option<PausableOperation>) {
+        self.assert_role(Role::Pauser);
+        let changed = match operation {
+            None => !std::mem::replace(&mut self.paused.all, true),
+            Some(operation) if !self.paused.operations.contains(&operation) => {
+                self.paused.operations.push(operation);
+                true
+            }
+            Some(_) => false,
+        };
+        if changed {
+            self.record_event(FactoryEvent::Paused { operation });
+        }
+    }
+
+    /// Lifts the pause set by `pause` with the same argument. Unpausing every
+    /// operation keeps the pauses of single operations.
+    pub fn unpause(&mut self, operation: Option<PausableOperation>) {
+        self.assert_role(Role::Pauser);
+        let changed = match operation {
+            None => std::mem::replace(&mut self.paused.all, false),
+            Some(operation) => {
+                let count = self.paused.operations.len();
+                self.paused.operations.retain(|paused| *paused != operation);
+                self.paused.operations.len() < count
+            }
+        };
+        if changed {
+            self.record_event(FactoryEvent::Unpaused { operation });
+        }
+    }
+
+    pub fn get_pause_state(&self) -> PauseState {
+        self.paused.clone()
+    }
+
+    pub fn is_paused(&self, operation: PausableOperation) -> bool {
+        self.paused.is_paused(operation)
+    }
+}
+
+impl TokenFactory {
+    pub(crate) fn assert_not_paused(&self, operation: PausableOperation) {
+        require(
+            !self.paused.is_paused(operation),
+            FactoryError::OperationPaused(operation),
+        );
+    }
+}
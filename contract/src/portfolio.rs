@@ -0,0 +1,112 @@
+use crate::callbacks::{ext_deployer, ext_ft};
+use crate::*;
+
+const PORTFOLIO_QUERY_GAS: Gas = 5_000_000_000_000;
+/// Tokens queried per call, each costs two queries out of the 300 TGas of a call
+pub const MAX_PORTFOLIO_TOKENS: u64 = 20;
+
+/// Subset of the deployer's `check_account` response used by the portfolio.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DeployerAccount {
+    pub allocated_num: WrappedBalance,
+    pub claimed: WrappedBalance,
+    pub claimable_amount: WrappedBalance,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PortfolioItem {
+    pub ft_contract: TokenId,
+    pub ft_deployer: AccountId,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+
+    // None when the remote call failed
+    pub allocated: Option<WrappedBalance>,
+    pub claimed: Option<WrappedBalance>,
+    pub claimable: Option<WrappedBalance>,
+    pub balance: Option<WrappedBalance>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Portfolio {
+    pub account_id: AccountId,
+    pub tokens: Vec<PortfolioItem>,
+    // `from_id` of the next page, None on the last one
+    pub next_cursor: Option<u64>,
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Queries the deployer and FT contract of every issued token of `account_id`
+    /// and aggregates the results in `on_portfolio_fetched`, up to
+    /// `MAX_PORTFOLIO_TOKENS` per call. Must be sent as a transaction since it
+    /// makes cross-contract calls.
+    pub fn get_portfolio(
+        &self,
+        account_id: AccountId,
+        from_id: Option<u64>,
+        limit: u64,
+    ) -> Promise {
+        let page = self.list_account_token_ids(
+            account_id.clone(),
+            from_id,
+            std::cmp::min(limit, MAX_PORTFOLIO_TOKENS),
+        );
+        let token_ids: Vec<TokenId> = page
+            .items
+            .into_iter()
+            .filter(|token_id| {
+                self.internal_find_token(token_id)
+                    .map(|token| token.allocation_initialized == 1)
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        let mut queries: Option<Promise> = None;
+        for token_id in token_ids.iter() {
+            let token = self.internal_get_token(token_id);
+            let query = ext_deployer::check_account(
+                account_id.clone(),
+                &token.ft_deployer,
+                0,
+                PORTFOLIO_QUERY_GAS,
+            )
+            .and(ext_ft::ft_balance_of(
+                account_id.clone(),
+                token_id,
+                0,
+                PORTFOLIO_QUERY_GAS,
+            ));
+            queries = Some(match queries {
+                Some(q) => q.and(query),
+                None => query,
+            });
+        }
+
+        let callback = ext_self::on_portfolio_fetched(
+            account_id,
+            token_ids,
+            page.next_cursor,
+            &env::current_account_id(),
+            0,
+            DEFAULT_GAS_FEE,
+        );
+
+        match queries {
+            Some(q) => q.then(callback),
+            None => callback,
+        }
+    }
+}
+
+pub(crate) fn promise_result_json<T: near_sdk::serde::de::DeserializeOwned>(
+    index: u64,
+) -> Option<T> {
+    match env::promise_result(index) {
+        PromiseResult::Successful(data) => near_sdk::serde_json::from_slice(&data).ok(),
+        _ => None,
+    }
+}
@@ -0,0 +1,120 @@
+use crate::errors::{require, FactoryError};
+use crate::roles::Role;
+use crate::*;
+
+/// A registration made while the factory is curated, waiting for a moderator
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ReviewRequest {
+    pub creator: AccountId,
+    pub requested_at: Timestamp,
+}
+
+impl ReviewRequest {
+    pub fn new(creator: AccountId) -> Self {
+        ReviewRequest {
+            creator,
+            requested_at: env::block_timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingReview {
+    pub ft_contract: TokenId,
+    pub creator: AccountId,
+    pub requested_at: WrappedTimestamp,
+}
+
+impl PendingReview {
+    fn new(ft_contract: TokenId, request: ReviewRequest) -> Self {
+        PendingReview {
+            ft_contract,
+            creator: request.creator,
+            requested_at: WrappedTimestamp::from(request.requested_at),
+        }
+    }
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// While curated, new registrations can not be deployed before a moderator
+    /// approves them. Turning it off keeps the pending registrations queued.
+    pub fn set_curated(&mut self, curated: bool) {
+        self.assert_owner_id();
+        if self.curated != curated {
+            self.curated = curated;
+            self.record_event(FactoryEvent::CurationChanged { curated });
+        }
+    }
+
+    pub fn is_curated(&self) -> bool {
+        self.curated
+    }
+
+    /// Lets the creator run the deployment steps
+    pub fn approve_token(&mut self, ft_contract: AccountId) {
+        self.assert_role(Role::Moderator);
+        self.internal_take_review_request(&ft_contract);
+        self.record_event(FactoryEvent::TokenApproved { ft_contract });
+    }
+
+    /// Unregisters a pending registration and refunds what its creator paid.
+    /// Nothing is deployed before approval, so the account funding is unspent.
+    pub fn reject_token(&mut self, ft_contract: AccountId, reason: String) {
+        self.assert_role(Role::Moderator);
+        self.assert_no_migration();
+        let request = self.internal_take_review_request(&ft_contract);
+        let mut token = self.internal_get_token(&ft_contract);
+        let refund: Balance = token
+            .cost
+            .as_ref()
+            .map(|cost| cost.total.into())
+            .unwrap_or(0);
+
+        // registrations hold at most MAX_ALLOCATEES allocations, as many as
+        // `register` inserted in one call
+        self.internal_remove_allocations(&mut token, u64::MAX);
+        self.internal_remove_token(token);
+        if refund > 0 {
+            Promise::new(request.creator).transfer(refund);
+        }
+
+        self.record_event(FactoryEvent::TokenRejected {
+            ft_contract,
+            reason,
+            refund: WrappedBalance::from(refund),
+        });
+    }
+
+    pub fn get_pending_review(&self, ft_contract: AccountId) -> Option<PendingReview> {
+        self.review_queue
+            .get(&ft_contract)
+            .map(|request| PendingReview::new(ft_contract, request))
+    }
+
+    /// Registrations waiting for a moderator
+    pub fn get_review_queue(&self, from_index: u64, limit: u64) -> Vec<PendingReview> {
+        self.review_queue
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(ft_contract, request)| PendingReview::new(ft_contract, request))
+            .collect()
+    }
+}
+
+impl TokenFactory {
+    pub(crate) fn assert_approved(&self, ft_contract: &TokenId) {
+        require(
+            self.review_queue.get(ft_contract).is_none(),
+            FactoryError::AwaitingApproval,
+        );
+    }
+
+    fn internal_take_review_request(&mut self, ft_contract: &TokenId) -> ReviewRequest {
+        self.review_queue
+            .remove(ft_contract)
+            .unwrap_or_else(|| FactoryError::NotAwaitingApproval.panic())
+    }
+}
@@ -0,0 +1,98 @@
+use crate::errors::{require, FactoryError};
+use crate::storage::StorageKey;
+use crate::*;
+
+/// Named permissions granted by the owner. The owner passes every role check.
+#[derive(
+    BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    // unregisters tokens, clears their metadata and resets the registry
+    Operator,
+    // curates listings: reviews registrations and sets moderation statuses
+    Moderator,
+    // sets the registration fee and account funding
+    FeeManager,
+    // starts and runs data migrations
+    Migrator,
+    // pauses and unpauses operations
+    Pauser,
+    // cancels scheduled admin operations before they run
+    Guardian,
+}
+
+pub const ROLES: [Role; 6] = [
+    Role::Operator,
+    Role::Moderator,
+    Role::FeeManager,
+    Role::Migrator,
+    Role::Pauser,
+    Role::Guardian,
+];
+
+#[near_bindgen]
+impl TokenFactory {
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_owner_id();
+        let mut members = self.internal_role_members(role);
+        if members.insert(&account_id) {
+            self.roles.insert(&role, &members);
+            self.record_event(FactoryEvent::RoleGranted { role, account_id });
+        }
+    }
+
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_owner_id();
+        let mut members = self.internal_role_members(role);
+        if members.remove(&account_id) {
+            if members.is_empty() {
+                self.roles.remove(&role);
+            } else {
+                self.roles.insert(&role, &members);
+            }
+            self.record_event(FactoryEvent::RoleRevoked { role, account_id });
+        }
+    }
+
+    pub fn has_role(&self, role: Role, account_id: AccountId) -> bool {
+        self.internal_has_role(role, &account_id)
+    }
+
+    /// Roles granted to `account_id`, not counting those the owner holds implicitly
+    pub fn get_account_roles(&self, account_id: AccountId) -> Vec<Role> {
+        ROLES
+            .iter()
+            .copied()
+            .filter(|role| self.internal_role_members(*role).contains(&account_id))
+            .collect()
+    }
+
+    pub fn get_role_members(&self, role: Role, from_index: u64, limit: u64) -> Vec<AccountId> {
+        self.internal_role_members(role)
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .collect()
+    }
+}
+
+impl TokenFactory {
+    pub(crate) fn assert_role(&self, role: Role) {
+        require(
+            self.internal_has_role(role, &env::predecessor_account_id()),
+            FactoryError::MissingRole(role),
+        );
+    }
+
+    pub(crate) fn internal_has_role(&self, role: Role, account_id: &AccountId) -> bool {
+        *account_id == self.owner_id || self.internal_role_members(role).contains(account_id)
+    }
+
+    fn internal_role_members(&self, role: Role) -> UnorderedSet<AccountId> {
+        self.roles
+            .get(&role)
+            .unwrap_or_else(|| UnorderedSet::new(StorageKey::RoleMembers { role }))
+    }
+}
@@ -0,0 +1,83 @@
+use near_sdk::{BorshStorageKey, CryptoHash};
+
+use crate::roles::Role;
+use crate::*;
+
+/// Prefixes of every collection. The variant tag separates the domains and
+/// nested collections are keyed by a fixed-length hash, so no prefix is the
+/// prefix of another. Registries created before this scheme keep their
+/// top-level literals (`b"tokenspec"`, ...); those start with a letter and
+/// can not collide with the tags either.
+#[derive(BorshStorageKey, BorshSerialize)]
+pub(crate) enum StorageKey {
+    Tokens,
+    UserTokenMap,
+    CreatorTokenMap,
+    TokenOrder,
+    Changes,
+    Allocations { token_hash: CryptoHash },
+    UserTokens { account_hash: CryptoHash },
+    CreatorTokens { account_hash: CryptoHash },
+    Roles,
+    RoleMembers { role: Role },
+    ScheduledOperations,
+    ReviewQueue,
+    Moderation,
+    ModerationHistory { token_hash: CryptoHash },
+    AllowedCreators,
+    CreatorUsage,
+    CompactedAllocatees,
+    TokenAllocatees { token_hash: CryptoHash },
+}
+
+impl StorageKey {
+    /// Allocations of one registration, ids are never reused
+    pub(crate) fn allocations(id: u64, ft_contract: &TokenId) -> Self {
+        StorageKey::Allocations {
+            token_hash: hash(&(id, ft_contract).try_to_vec().unwrap()),
+        }
+    }
+
+    /// Moderation history of one registration, keyed like its allocations
+    pub(crate) fn moderation_history(id: u64, ft_contract: &TokenId) -> Self {
+        StorageKey::ModerationHistory {
+            token_hash: hash(&(id, ft_contract).try_to_vec().unwrap()),
+        }
+    }
+
+    /// Allocatees of one registration whose allocations compaction dropped
+    pub(crate) fn compacted_allocatees(id: u64, ft_contract: &TokenId) -> Self {
+        StorageKey::TokenAllocatees {
+            token_hash: hash(&(id, ft_contract).try_to_vec().unwrap()),
+        }
+    }
+
+    pub(crate) fn user_tokens(account_id: &AccountId) -> Self {
+        StorageKey::UserTokens {
+            account_hash: hash(account_id.as_bytes()),
+        }
+    }
+
+    pub(crate) fn creator_tokens(creator: &AccountId) -> Self {
+        StorageKey::CreatorTokens {
+            account_hash: hash(creator.as_bytes()),
+        }
+    }
+}
+
+fn hash(value: &[u8]) -> CryptoHash {
+    let mut result = CryptoHash::default();
+    result.copy_from_slice(&env::sha256(value));
+    result
+}
+
+/// Whether a map or set was created with `key`. Both serialize their element
+/// index prefix (`key` followed by `b'i'`) first, as a length-prefixed vector.
+pub(crate) fn uses_storage_key<T: BorshSerialize>(collection: &T, key: &StorageKey) -> bool {
+    let mut prefix = key.try_to_vec().unwrap();
+    prefix.push(b'i');
+    collection
+        .try_to_vec()
+        .unwrap()
+        .starts_with(&prefix.try_to_vec().unwrap())
+}
@@ -0,0 +1,196 @@
+use crate::admins::CleanupReport;
+use crate::errors::{require, FactoryError};
+use crate::roles::Role;
+use crate::*;
+
+pub const DEFAULT_TIMELOCK_DELAY: Duration = 2 * 86_400_000_000_000; // 2 days
+/// Shortest delay the owner can set, so guardians always get time to cancel
+pub const MIN_TIMELOCK_DELAY: Duration = 86_400_000_000_000; // 1 day
+
+/// Admin operations that delete or rewrite data. They are scheduled first and
+/// run once `timelock_delay` has passed, giving a guardian time to cancel them.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdminOperation {
+    Reset,
+    Unregister { ft_contract: TokenId },
+    ClearMetadata { ft_contract: TokenId },
+    StartMigration { target_version: Option<u32> },
+}
+
+impl AdminOperation {
+    /// Role needed to schedule and to execute the operation
+    pub fn role(&self) -> Role {
+        match self {
+            AdminOperation::Reset
+            | AdminOperation::Unregister { .. }
+            | AdminOperation::ClearMetadata { .. } => Role::Operator,
+            AdminOperation::StartMigration { .. } => Role::Migrator,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ScheduledOperation {
+    pub operation: AdminOperation,
+    pub scheduled_by: AccountId,
+    pub scheduled_at: Timestamp,
+    pub executable_at: Timestamp,
+    // a paginated operation is started by its first call, it can no longer be cancelled
+    pub started: bool,
+    // tokens a `Reset` removed over its previous calls
+    pub removed_tokens: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WrappedScheduledOperation {
+    pub id: u64,
+    pub operation: AdminOperation,
+    pub scheduled_by: AccountId,
+    pub scheduled_at: WrappedTimestamp,
+    pub executable_at: WrappedTimestamp,
+    pub started: bool,
+}
+
+impl WrappedScheduledOperation {
+    fn new(id: u64, scheduled: ScheduledOperation) -> Self {
+        WrappedScheduledOperation {
+            id,
+            operation: scheduled.operation,
+            scheduled_by: scheduled.scheduled_by,
+            scheduled_at: WrappedTimestamp::from(scheduled.scheduled_at),
+            executable_at: WrappedTimestamp::from(scheduled.executable_at),
+            started: scheduled.started,
+        }
+    }
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Schedules `operation`, which can be executed after `timelock_delay`.
+    /// Returns its id.
+    pub fn schedule_operation(&mut self, operation: AdminOperation) -> u64 {
+        self.assert_role(operation.role());
+        let id = self.next_operation_id;
+        self.next_operation_id += 1;
+        let executable_at = env::block_timestamp() + self.timelock_delay;
+        self.scheduled_operations.insert(
+            &id,
+            &ScheduledOperation {
+                operation: operation.clone(),
+                scheduled_by: env::predecessor_account_id(),
+                scheduled_at: env::block_timestamp(),
+                executable_at,
+                started: false,
+                removed_tokens: 0,
+            },
+        );
+
+        self.record_event(FactoryEvent::OperationScheduled {
+            id,
+            operation,
+            executable_at: WrappedTimestamp::from(executable_at),
+        });
+        id
+    }
+
+    /// Drops a scheduled operation that has not started yet
+    pub fn cancel_operation(&mut self, id: u64) {
+        self.assert_role(Role::Guardian);
+        let scheduled = self.internal_get_scheduled_operation(id);
+        require(!scheduled.started, FactoryError::OperationAlreadyStarted);
+
+        self.scheduled_operations.remove(&id);
+        self.record_event(FactoryEvent::OperationCancelled { id });
+    }
+
+    /// Runs a scheduled operation once its delay has passed. Paginated
+    /// operations delete up to `limit` allocations per call and stay
+    /// scheduled until a call reports `done`.
+    pub fn execute_operation(&mut self, id: u64, limit: Option<u64>) -> CleanupReport {
+        let mut scheduled = self.internal_get_scheduled_operation(id);
+        self.assert_role(scheduled.operation.role());
+        require(
+            env::block_timestamp() >= scheduled.executable_at,
+            FactoryError::OperationNotReady,
+        );
+
+        let report = match scheduled.operation.clone() {
+            AdminOperation::Reset => self.internal_reset(limit, &mut scheduled.removed_tokens),
+            // a started operation can not be cancelled, once its token was
+            // removed another way there is nothing left to do
+            AdminOperation::Unregister { ft_contract }
+            | AdminOperation::ClearMetadata { ft_contract }
+                if scheduled.started && self.internal_find_token(&ft_contract).is_none() =>
+            {
+                CleanupReport::new(true, env::storage_usage())
+            }
+            AdminOperation::Unregister { ft_contract } => {
+                self.internal_unregister(ft_contract, limit)
+            }
+            AdminOperation::ClearMetadata { ft_contract } => {
+                self.internal_clear_metadata(ft_contract, limit)
+            }
+            AdminOperation::StartMigration { target_version } => {
+                let storage_before = env::storage_usage();
+                self.internal_start_migration(target_version);
+                CleanupReport::new(true, storage_before)
+            }
+        };
+
+        if report.done {
+            self.scheduled_operations.remove(&id);
+            self.record_event(FactoryEvent::OperationExecuted { id });
+        } else {
+            scheduled.started = true;
+            self.scheduled_operations.insert(&id, &scheduled);
+        }
+        report
+    }
+
+    /// Applies to operations scheduled from now on. Can not be shorter than
+    /// `MIN_TIMELOCK_DELAY`.
+    pub fn set_timelock_delay(&mut self, delay: WrappedDuration) {
+        self.assert_owner_id();
+        require(
+            u64::from(delay) >= MIN_TIMELOCK_DELAY,
+            FactoryError::TimelockDelayTooShort,
+        );
+        self.timelock_delay = delay.into();
+        self.record_event(FactoryEvent::TimelockDelayChanged { delay });
+    }
+
+    pub fn get_timelock_delay(&self) -> WrappedDuration {
+        WrappedDuration::from(self.timelock_delay)
+    }
+
+    pub fn get_scheduled_operation(&self, id: u64) -> Option<WrappedScheduledOperation> {
+        self.scheduled_operations
+            .get(&id)
+            .map(|scheduled| WrappedScheduledOperation::new(id, scheduled))
+    }
+
+    /// Operations scheduled and not yet executed or cancelled
+    pub fn list_scheduled_operations(
+        &self,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<WrappedScheduledOperation> {
+        self.scheduled_operations
+            .iter()
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .map(|(id, scheduled)| WrappedScheduledOperation::new(id, scheduled))
+            .collect()
+    }
+}
+
+impl TokenFactory {
+    fn internal_get_scheduled_operation(&self, id: u64) -> ScheduledOperation {
+        self.scheduled_operations
+            .get(&id)
+            .unwrap_or_else(|| FactoryError::OperationNotFound.panic())
+    }
+}
@@ -0,0 +1,233 @@
+use crate::errors::FactoryError;
+use crate::*;
+
+const MAX_DECIMALS: u8 = 24;
+const REFERENCE_HASH_LEN: usize = 32;
+pub const MAX_ALLOCATEES: usize = 500;
+pub const MAX_VESTING_DURATION: Duration = 10 * 365 * 86_400_000_000_000; // 10 years
+
+/// Arguments of `register`, shared with `validate_registration`
+pub struct RegistrationInput {
+    pub ft_contract: AccountId,
+    pub deployer_contract: AccountId,
+    pub total_supply: Balance,
+    pub token_name: String,
+    pub symbol: String,
+    pub icon: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<Base64VecU8>,
+    pub allocations: TokenAllocationInput,
+    pub decimals: u8,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidationIssue {
+    pub code: u16,
+    pub message: String,
+    // allocatee the issue refers to, if any
+    pub account_id: Option<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ValidationReport {
+    pub valid: bool,
+    pub issues: Vec<ValidationIssue>,
+    pub required_deposit: WrappedBalance,
+}
+
+#[near_bindgen]
+impl TokenFactory {
+    /// Runs every `register` check without stopping at the first problem.
+    /// `creator` is the account that will sign `register`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn validate_registration(
+        &self,
+        ft_contract: AccountId,
+        deployer_contract: AccountId,
+        total_supply: WrappedBalance,
+        token_name: String,
+        symbol: String,
+        icon: Option<String>,
+        reference: Option<String>,
+        reference_hash: Option<Base64VecU8>,
+        allocations: TokenAllocationInput,
+        decimals: u8,
+        creator: AccountId,
+    ) -> ValidationReport {
+        let input = RegistrationInput {
+            ft_contract,
+            deployer_contract,
+            total_supply: total_supply.into(),
+            token_name,
+            symbol,
+            icon,
+            reference,
+            reference_hash,
+            allocations,
+            decimals,
+        };
+        let required_deposit = self.internal_quote_registration(&input, creator).total;
+        let issues: Vec<ValidationIssue> = self
+            .validate_registration_input(&input)
+            .into_iter()
+            .map(|(error, account_id)| ValidationIssue {
+                code: error.code(),
+                message: error.message(),
+                account_id,
+            })
+            .collect();
+
+        ValidationReport {
+            valid: issues.is_empty(),
+            issues,
+            required_deposit,
+        }
+    }
+}
+
+impl TokenFactory {
+    /// Every problem of a registration. The deposit is checked by `register`
+    /// once the storage it uses is known.
+    pub(crate) fn validate_registration_input(
+        &self,
+        input: &RegistrationInput,
+    ) -> Vec<(FactoryError, Option<AccountId>)> {
+        let mut issues = vec![];
+        let mut check = |condition: bool, error: FactoryError| {
+            if !condition {
+                issues.push((error, None));
+            }
+        };
+
+        // metadata
+        check(input.total_supply > 0, FactoryError::ZeroTotalSupply);
+        check(
+            !input.token_name.trim().is_empty(),
+            FactoryError::EmptyTokenName,
+        );
+        check(!input.symbol.trim().is_empty(), FactoryError::EmptySymbol);
+        check(
+            input.decimals <= MAX_DECIMALS,
+            FactoryError::InvalidDecimals,
+        );
+        check(
+            match &input.reference_hash {
+                Some(hash) => hash.0.len() == REFERENCE_HASH_LEN && input.reference.is_some(),
+                None => true,
+            },
+            FactoryError::InvalidReferenceHash,
+        );
+        check(
+            input
+                .icon
+                .as_ref()
+                .map(|icon| !icon.is_empty())
+                .unwrap_or(true),
+            FactoryError::EmptyIcon,
+        );
+
+        // accounts
+        check(
+            env::is_valid_account_id(input.ft_contract.as_bytes()),
+            FactoryError::InvalidFtContract,
+        );
+        check(
+            env::is_valid_account_id(input.deployer_contract.as_bytes()),
+            FactoryError::InvalidFtDeployer,
+        );
+        check(
+            input.ft_contract != input.deployer_contract,
+            FactoryError::SameContractAccounts,
+        );
+        check(
+            self.internal_find_token(&input.ft_contract).is_none(),
+            FactoryError::TokenAlreadyRegistered,
+        );
+
+        // allocations
+        let total_allocs: u64 = input.allocations.values().fold(0, |total, alloc| {
+            total.saturating_add(alloc.allocated_percent)
+        });
+        check(
+            total_allocs <= MAX_SUPPLY_PERCENT,
+            FactoryError::AllocationsExceedSupply,
+        );
+        check(
+            total_allocs >= MAX_SUPPLY_PERCENT,
+            FactoryError::AllocationsNotFullSupply,
+        );
+        check(
+            input.allocations.len() <= MAX_ALLOCATEES,
+            FactoryError::TooManyAllocatees,
+        );
+        check(
+            input
+                .allocations
+                .get(TOKENHUB_TREASURY)
+                .map(|alloc| alloc.allocated_percent > 0)
+                .unwrap_or(false),
+            FactoryError::TreasuryAllocationMissing,
+        );
+
+        let mut account_ids: Vec<&AccountId> = input.allocations.keys().collect();
+        account_ids.sort();
+        for account_id in account_ids {
+            if !env::is_valid_account_id(account_id.as_bytes()) {
+                issues.push((
+                    FactoryError::InvalidAllocationAccount,
+                    Some(account_id.clone()),
+                ));
+            }
+            let allocation = TokenAllocation::from(input.allocations[account_id].clone());
+            for error in validate_allocation(&allocation, Some(env::block_timestamp())) {
+                issues.push((error, Some(account_id.clone())));
+            }
+        }
+
+        issues
+    }
+}
+
+/// Problems of a single allocation. The start time is only checked against `now`
+/// when given, since allocations of registered tokens may have started already.
+pub(crate) fn validate_allocation(
+    allocation: &TokenAllocation,
+    now: Option<Timestamp>,
+) -> Vec<FactoryError> {
+    let mut issues = vec![];
+    if allocation.allocated_percent == 0 {
+        issues.push(FactoryError::ZeroAllocation);
+    }
+    if allocation.allocated_percent < allocation.claimed {
+        issues.push(FactoryError::AllocationBelowClaimed);
+    }
+    if allocation.initial_release > allocation.allocated_percent {
+        issues.push(FactoryError::InitialReleaseExceedsAllocation);
+    }
+    if allocation.vesting_interval == 0 {
+        issues.push(FactoryError::ZeroVestingInterval);
+    }
+    if let Some(now) = now {
+        if allocation.vesting_start_time < now {
+            issues.push(FactoryError::VestingStartInPast);
+        }
+    }
+
+    match allocation
+        .vesting_end_time
+        .checked_sub(allocation.vesting_start_time)
+    {
+        None => issues.push(FactoryError::VestingEndBeforeStart),
+        Some(duration) => {
+            if allocation.vesting_interval > duration {
+                issues.push(FactoryError::VestingIntervalTooLong);
+            }
+            if duration > MAX_VESTING_DURATION {
+                issues.push(FactoryError::VestingDurationTooLong);
+            }
+        }
+    }
+    issues
+}
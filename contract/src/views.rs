@@ -1,82 +1,188 @@
+use std::ops::Bound;
+
+use crate::errors::{require, FactoryError};
+use crate::moderation::ModerationStatus;
 use crate::*;
 
 #[near_bindgen]
 impl TokenFactory {
-    pub fn get_token_state(self, ft_contract: AccountId) -> WrappedState {
-        let token = self.tokens.get(&ft_contract).unwrap_or_default();
-        assert!(
-            token.ft_contract != String::from("__default_value__"),
-            "Token is not registered",
-        );
-        WrappedState::from(token)
+    pub fn get_token_state(&self, ft_contract: AccountId) -> Option<WrappedState> {
+        self.internal_find_token(&ft_contract)
+            .map(WrappedState::from)
+    }
+
+    pub fn get_token_allocations(
+        &self,
+        ft_contract: AccountId,
+        from_index: u64,
+        limit: u64,
+    ) -> Vec<(AccountId, WrappedTokenAllocation)> {
+        let token = self.internal_get_token(&ft_contract);
+        let keys = token.allocations.keys_as_vector();
+        let values = token.allocations.values_as_vector();
+
+        (from_index..std::cmp::min(from_index.saturating_add(limit), keys.len()))
+            .map(|index| {
+                (
+                    keys.get(index).unwrap(),
+                    WrappedTokenAllocation::from(values.get(index).unwrap()),
+                )
+            })
+            .collect()
     }
 
     pub fn list_tokens_by_account_id(&self, account_id: AccountId) -> Vec<TokenId> {
-        assert!(env::state_exists(), "The contract is not initialized");
+        require(env::state_exists(), FactoryError::NotInitialized);
 
-        if let Some(token_ids) = self.user_token_map.get(&account_id) {
-            return token_ids.to_vec();
+        match self.user_token_map.get(&account_id) {
+            Some(token_ids) => token_ids.iter().map(|(_, token)| token).collect(),
+            None => vec![],
         }
-        vec![]
     }
 
-    pub fn list_token_contracts(&self, from_index: u64, limit: u64) -> Vec<WrappedState> {
-        assert!(env::state_exists(), "The contract is not initialized");
+    pub fn list_account_token_ids(
+        &self,
+        account_id: AccountId,
+        from_id: Option<u64>,
+        limit: u64,
+    ) -> Page<TokenId> {
+        require(env::state_exists(), FactoryError::NotInitialized);
 
-        let keys = self.tokens.keys_as_vector();
+        let (items, next_cursor) =
+            paginate_token_index(self.user_token_map.get(&account_id), from_id, limit);
+        Page { items, next_cursor }
+    }
 
-        let from = if keys.len() > (limit + from_index) {
-            keys.len() - limit - from_index
-        } else {
-            0
-        };
+    /// Allocations of `account_id` across its tokens, read from the factory state.
+    /// Compacted tokens are listed without allocation, see `get_portfolio`.
+    pub fn list_allocations_by_account_id(
+        &self,
+        account_id: AccountId,
+        from_id: Option<u64>,
+        limit: u64,
+    ) -> Page<AccountAllocation> {
+        require(env::state_exists(), FactoryError::NotInitialized);
 
-        let to = if keys.len() > from_index {
-            keys.len() - from_index
-        } else {
-            0
-        };
-
-        (from..to)
-            .map(|index| {
-                let key = keys.get(index).unwrap();
-                let state = self.tokens.get(&key).unwrap();
-                WrappedState::from(state)
+        let (token_ids, next_cursor) =
+            paginate_token_index(self.user_token_map.get(&account_id), from_id, limit);
+        let items = token_ids
+            .into_iter()
+            .filter_map(|ft_contract| {
+                let token = self.internal_find_token(&ft_contract)?;
+                let allocation = token.allocations.get(&account_id);
+                if allocation.is_none() && token.compacted.is_none() {
+                    return None;
+                }
+                Some(AccountAllocation {
+                    symbol: token.symbol(),
+                    decimals: token.decimals(),
+                    status: token.status(),
+                    allocated_amount: allocation.as_ref().map(|allocation| {
+                        WrappedBalance::from(token.amount_of(allocation.allocated_percent))
+                    }),
+                    allocation: allocation.map(WrappedTokenAllocation::from),
+                    ft_contract,
+                })
             })
-            .rev()
-            .collect()
+            .collect();
+
+        Page { items, next_cursor }
+    }
+
+    pub fn get_token_count(&self) -> u64 {
+        self.tokens.len()
     }
 
-    pub fn list_token_states(&self, token_contracts: Vec<AccountId>) -> Vec<WrappedState> {
-        assert!(env::state_exists(), "The contract is not initialized");
-        let mut result = vec![];
-        for token in token_contracts.iter() {
-            let state = self.tokens.get(&token).unwrap_or_default();
+    /// Tokens ordered by numeric id (creation order), starting at `from_id` (inclusive).
+    /// Only tokens in `moderation_status` are listed when it is given, and
+    /// delisted ones are left out with `hide_delisted`.
+    pub fn list_tokens(
+        &self,
+        from_id: Option<u64>,
+        limit: u64,
+        newest_first: Option<bool>,
+        moderation_status: Option<ModerationStatus>,
+        hide_delisted: Option<bool>,
+    ) -> Page<TokenSummary> {
+        require(env::state_exists(), FactoryError::NotInitialized);
 
-            result.push(WrappedState::from(state));
+        let (token_ids, next_cursor) =
+            self.paginate_tokens(from_id, limit, newest_first.unwrap_or(false), |token| {
+                self.internal_is_listed(token, moderation_status, hide_delisted.unwrap_or(false))
+            });
+        Page {
+            items: token_ids
+                .iter()
+                .filter_map(|token| self.internal_find_token(token))
+                .map(|token| self.internal_token_summary(token))
+                .collect(),
+            next_cursor,
         }
-        return result;
     }
 
-    //NOTE: Use for the old version
-    pub fn list_all_tokens(&self) -> Vec<WrappedState> {
-        assert!(env::state_exists(), "The contract is not initialized");
+    /// Filtered like `list_tokens`
+    pub fn list_token_ids(
+        &self,
+        from_id: Option<u64>,
+        limit: u64,
+        newest_first: Option<bool>,
+        moderation_status: Option<ModerationStatus>,
+        hide_delisted: Option<bool>,
+    ) -> Page<TokenId> {
+        require(env::state_exists(), FactoryError::NotInitialized);
 
-        let token_list = self.tokens.keys_as_vector();
-        let mut result = vec![];
+        let (items, next_cursor) =
+            self.paginate_tokens(from_id, limit, newest_first.unwrap_or(false), |token| {
+                self.internal_is_listed(token, moderation_status, hide_delisted.unwrap_or(false))
+            });
+        Page { items, next_cursor }
+    }
 
-        for token in token_list.iter() {
-            let state = self.tokens.get(&token).unwrap_or_default();
-            // let e = json!({state.ft_contract});
-            let e = WrappedState::from(state);
-            result.push(e);
-        }
+    /// Deprecated: offsets shift while tokens are being registered, use `list_tokens`
+    pub fn list_token_contracts(
+        &self,
+        from_index: u64,
+        limit: u64,
+        hide_delisted: Option<bool>,
+    ) -> Vec<TokenSummary> {
+        require(env::state_exists(), FactoryError::NotInitialized);
 
-        return result;
+        self.token_order
+            .iter_rev()
+            .filter(|(_, token)| {
+                self.internal_is_listed(token, None, hide_delisted.unwrap_or(false))
+            })
+            .skip(from_index as usize)
+            .take(limit as usize)
+            .filter_map(|(_, token)| self.internal_find_token(&token))
+            .map(|token| self.internal_token_summary(token))
+            .collect()
     }
 
+    /// One entry per requested contract, None for unknown ones
+    pub fn list_token_states(&self, token_contracts: Vec<AccountId>) -> Vec<Option<WrappedState>> {
+        require(env::state_exists(), FactoryError::NotInitialized);
+        token_contracts
+            .iter()
+            .map(|token| self.internal_find_token(token).map(WrappedState::from))
+            .collect()
+    }
+
+    //NOTE: Use for the old version
+    /// Deprecated: unbounded, use `list_tokens`
+    pub fn list_all_tokens(&self) -> Vec<TokenSummary> {
+        require(env::state_exists(), FactoryError::NotInitialized);
+
+        self.tokens
+            .keys()
+            .filter_map(|token| self.internal_find_token(&token))
+            .map(|token| self.internal_token_summary(token))
+            .collect()
+    }
+
+    /// Deprecated: unbounded, use `list_token_ids`
     pub fn list_all_token_contracts(self) -> Value {
-        assert!(env::state_exists(), "The contract is not initialized");
+        require(env::state_exists(), FactoryError::NotInitialized);
 
         let token_list = self.tokens.keys_as_vector();
         let mut result: Value = json!([]);
@@ -84,24 +190,117 @@ impl TokenFactory {
         for token in token_list.iter() {
             result.as_array_mut().unwrap().push(json!(token));
         }
-        return result;
+        result
     }
 
-    pub fn list_my_tokens(&self, account_id: AccountId) -> Vec<WrappedState> {
-        assert!(env::state_exists(), "The contract is not initialized");
+    /// Deprecated: unbounded, use `list_tokens_by_creator`
+    pub fn list_my_tokens(&self, account_id: AccountId) -> Vec<TokenSummary> {
+        require(env::state_exists(), FactoryError::NotInitialized);
 
-        let token_list = self.tokens.keys_as_vector();
-        let mut result = vec![];
+        match self.creator_token_map.get(&account_id) {
+            Some(token_ids) => token_ids
+                .iter()
+                .filter_map(|(_, token)| self.internal_find_token(&token))
+                .map(|token| self.internal_token_summary(token))
+                .collect(),
+            None => vec![],
+        }
+    }
 
-        for token in token_list.iter() {
-            let state = self.tokens.get(&token).unwrap_or_default();
-            if state.creator.eq(&account_id) {
-                // let e = json!({state.ft_contract});
-                let e = WrappedState::from(state);
-                result.push(e);
-            }
+    pub fn list_tokens_by_creator(
+        &self,
+        creator: AccountId,
+        from_id: Option<u64>,
+        limit: u64,
+    ) -> Page<TokenSummary> {
+        require(env::state_exists(), FactoryError::NotInitialized);
+
+        let (token_ids, next_cursor) =
+            paginate_token_index(self.creator_token_map.get(&creator), from_id, limit);
+        Page {
+            items: token_ids
+                .iter()
+                .filter_map(|token| self.internal_find_token(token))
+                .map(|token| self.internal_token_summary(token))
+                .collect(),
+            next_cursor,
         }
+    }
+}
 
-        return result;
+impl TokenFactory {
+    fn internal_token_summary(&self, token: State) -> TokenSummary {
+        let moderation_status = self.internal_moderation_status(&token.ft_contract);
+        TokenSummary::new(token, moderation_status)
     }
+
+    /// Page of the whole registry by numeric token id, skipping the tokens
+    /// `listed` rejects
+    fn paginate_tokens(
+        &self,
+        from_id: Option<u64>,
+        limit: u64,
+        newest_first: bool,
+        listed: impl Fn(&TokenId) -> bool,
+    ) -> (Vec<TokenId>, Option<u64>) {
+        let take = limit as usize + 1;
+        let listed = |(_, token): &(u64, TokenId)| listed(token);
+        let mut page: Vec<(u64, TokenId)> = if newest_first {
+            match from_id.and_then(|id| id.checked_add(1)) {
+                Some(id) => self
+                    .token_order
+                    .iter_rev_from(id)
+                    .filter(listed)
+                    .take(take)
+                    .collect(),
+                None => self
+                    .token_order
+                    .iter_rev()
+                    .filter(listed)
+                    .take(take)
+                    .collect(),
+            }
+        } else {
+            self.token_order
+                .range((Bound::Included(from_id.unwrap_or(0)), Bound::Unbounded))
+                .filter(listed)
+                .take(take)
+                .collect()
+        };
+
+        let next_cursor = if page.len() > limit as usize {
+            page.pop().map(|(id, _)| id)
+        } else {
+            None
+        };
+        (
+            page.into_iter().map(|(_, token)| token).collect(),
+            next_cursor,
+        )
+    }
+}
+
+/// Page of a token index (per creator or per account) by numeric token id
+fn paginate_token_index(
+    index: Option<TreeMap<u64, TokenId>>,
+    from_id: Option<u64>,
+    limit: u64,
+) -> (Vec<TokenId>, Option<u64>) {
+    let mut page: Vec<(u64, TokenId)> = match index {
+        Some(index) => index
+            .range((Bound::Included(from_id.unwrap_or(0)), Bound::Unbounded))
+            .take(limit as usize + 1)
+            .collect(),
+        None => vec![],
+    };
+
+    let next_cursor = if page.len() > limit as usize {
+        page.pop().map(|(id, _)| id)
+    } else {
+        None
+    };
+    (
+        page.into_iter().map(|(_, token)| token).collect(),
+        next_cursor,
+    )
 }